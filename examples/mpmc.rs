@@ -3,6 +3,7 @@ use core::pin::Pin;
 use core::task::Poll::*;
 use core::task::{Context, Poll};
 use std::thread::spawn;
+use uio::time::{Duration, Timer};
 
 pub struct Producer {
     value: Option<u32>,
@@ -16,6 +17,12 @@ impl Producer {
     }
 }
 
+impl Default for Producer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Future for Producer {
     type Output = u32;
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
@@ -35,38 +42,26 @@ impl Future for Producer {
     }
 }
 
+#[derive(Copy, Clone)]
 struct Job {
     producer: u32,
     value: u32,
 }
 
-struct Delay {
-    millis: Option<u32>,
-}
-
-impl Unpin for Delay {}
-impl core::future::Future for Delay {
-    type Output = ();
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        if self.millis.is_none() {
-            Poll::Ready(())
-        } else {
-            let sleep_time = self.millis.take().unwrap();
-            let waker = cx.waker().clone();
-            spawn(move || {
-                std::thread::sleep(std::time::Duration::from_millis(sleep_time as u64));
-                waker.wake();
-            });
-            Poll::Pending
+impl Job {
+    pub const fn new() -> Self {
+        Self {
+            producer: 0,
+            value: 0,
         }
     }
 }
 
 async fn task_sleep(time: u32) {
-    Delay { millis: Some(time) }.await;
+    Timer::after(Duration::from_millis(time as u64)).await;
 }
 
-async fn consumer(id: u32, receiver: embedded_async::channel::Receiver<Job>) {
+async fn consumer(id: u32, receiver: uio::sync::Receiver<'_, Job>) {
     let mut job_count = 0i32;
     'main_loop: loop {
         match receiver.recv().await {
@@ -88,7 +83,7 @@ async fn consumer(id: u32, receiver: embedded_async::channel::Receiver<Job>) {
     println!("{} consumed {} jobs", id, job_count);
 }
 
-async fn producer(id: u32, sender: embedded_async::channel::Sender<Job>) {
+async fn producer(id: u32, sender: uio::sync::Sender<'_, Job>) {
     for _ in 0..5 {
         let value = Producer { value: None }.await;
         println!("PRODUCER {} producing {}", id, value);
@@ -110,22 +105,36 @@ async fn producer(id: u32, sender: embedded_async::channel::Sender<Job>) {
     println!("PRODUCER {} stopping", id);
 }
 
+static CLOCK: uio::time::StdClock = uio::time::StdClock::new();
+static IDLE: uio::executor::idle::ThreadPark = uio::executor::idle::ThreadPark::new();
+
 fn main() {
-    use uio::task_start;
-    let job_channel = embedded_async::channel::Channel::<Job, embedded_async::channel::consts::U10>::new();
-    pin_utils::pin_mut!(job_channel);
+    use uio::{channel, task_decl};
+    unsafe { uio::time::set_clock(&CLOCK) };
+    unsafe { uio::executor::set_idle_strategy(&IDLE) };
+    channel!(job_channel, Job::new(), 10);
     // Get a sender and receiver to the channel
-    let (s1, recv1) = job_channel.split().unwrap();
+    let (s1, recv1) = job_channel.split();
 
-    task_start!(producer1, producer(1, s1.clone()));
-    task_start!(producer2, producer(2, s1.clone()));
-    task_start!(producer3, producer(3, s1.clone()));
-    task_start!(producer4, producer(4, s1.clone()));
-    task_start!(producer5, producer(5, s1.clone()));
-    task_start!(producer6, producer(6, s1));
+    task_decl!(producer1, producer(1, s1.clone()));
+    task_decl!(producer2, producer(2, s1.clone()));
+    task_decl!(producer3, producer(3, s1.clone()));
+    task_decl!(producer4, producer(4, s1.clone()));
+    task_decl!(producer5, producer(5, s1.clone()));
+    task_decl!(producer6, producer(6, s1));
 
-    task_start!(consumer1, consumer(1, recv1.clone()));
-    task_start!(consumer2, consumer(2, recv1));
+    task_decl!(consumer1, consumer(1, recv1.clone()));
+    task_decl!(consumer2, consumer(2, recv1));
 
-    uio::executor::run();
+    // The tasks borrow the channel, the scope runs them until they have finished.
+    uio::executor::scope(|s| {
+        s.start(producer1);
+        s.start(producer2);
+        s.start(producer3);
+        s.start(producer4);
+        s.start(producer5);
+        s.start(producer6);
+        s.start(consumer1);
+        s.start(consumer2);
+    });
 }
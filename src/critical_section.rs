@@ -0,0 +1,374 @@
+//! Critical sections
+//!
+//! All state shared between tasks, executors and interrupt handlers is updated either by
+//! read-modify-write operations or inside a critical section, both provided by the
+//! `CriticalSection` implementation selected with cargo features:
+//!
+//! * `InterruptMasking`, the default on bare metal targets (`target_os = "none"`), masks
+//!   interrupts. The `interrupt-critical-section` feature selects it on any target, and also does every
+//!   read-modify-write inside the critical section, for single core targets without CAS
+//!   like Cortex-M0. Targets without CAS always do so.
+//! * `Atomic` spins on a per-lock flag. It is the default on hosted targets, where threads
+//!   rather than interrupts preempt each other, and is selected on bare metal by the
+//!   `spin-critical-section` feature, for multi-core targets where masking interrupts
+//!   doesn't exclude the other cores.
+//! * `StdMutex`, selected by the `std-critical-section` feature, uses a global mutex.
+//!
+//! ## Interrupt safety
+//!
+//! Primitives documented as safe from interrupt handlers, and interrupt executors
+//! expiring timers, take crate internal locks from the handler. With `InterruptMasking`
+//! that is always safe, the handler can't preempt code holding the lock. With `Atomic`, a
+//! handler taking a lock held by the code it preempted on the same core spins forever, so
+//! they are only safe from a handler that can't preempt another user of the same
+//! primitive, or any user of timers for interrupt executors, on its core.
+//!
+//! The `ci.yml` workflow runs the test suite with each of them.
+
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::sync::PoisonError;
+
+/// Trait for critical section implementations.
+pub trait CriticalSection {
+    /// Run `f` while excluding every other context running `with` for the same `lock`.
+    ///
+    /// `lock` is cleared when not held. Implementations may ignore it and exclude all other
+    /// contexts instead, in which case nested calls must be supported.
+    fn with<R>(lock: &AtomicBool, f: impl FnOnce() -> R) -> R;
+}
+
+/// Critical sections spinning on a per-lock flag, with read-modify-write operations done
+/// by atomic compare-and-swap.
+///
+/// Not safe to share with interrupt handlers on the same core, see the module docs.
+#[cfg(target_has_atomic = "8")]
+pub struct Atomic;
+
+#[cfg(target_has_atomic = "8")]
+impl CriticalSection for Atomic {
+    fn with<R>(lock: &AtomicBool, f: impl FnOnce() -> R) -> R {
+        while lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let result = f();
+        lock.store(false, Ordering::Release);
+        result
+    }
+}
+
+/// Trait for masking interrupts, used by `InterruptMasking`.
+///
+/// ## Example
+///
+/// ```
+/// use core::sync::atomic::{AtomicBool, Ordering};
+/// use uio::critical_section::{set_interrupt_mask, InterruptMask};
+///
+/// /// Stand-in for the interrupt enable bit of a single core target.
+/// struct Primask(AtomicBool);
+///
+/// impl InterruptMask for Primask {
+///     fn disable(&self) -> bool {
+///         let unmasked = self.0.load(Ordering::Acquire);
+///         self.0.store(false, Ordering::Release);
+///         unmasked
+///     }
+///
+///     fn restore(&self, unmasked: bool) {
+///         if unmasked {
+///             self.0.store(true, Ordering::Release);
+///         }
+///     }
+/// }
+///
+/// static PRIMASK: Primask = Primask(AtomicBool::new(true));
+///
+/// fn main() {
+///     unsafe { set_interrupt_mask(&PRIMASK) };
+///     uio::task_start!(task, async { 5 });
+///     assert_eq!(uio::executor::run_until(task), Ok(5));
+///     assert!(PRIMASK.0.load(Ordering::Acquire));
+/// }
+/// ```
+pub trait InterruptMask: Sync {
+    /// Mask all interrupts, returning `true` if they were unmasked.
+    fn disable(&self) -> bool;
+
+    /// Unmask interrupts if `unmasked` is `true`, restoring the state before `disable()`.
+    fn restore(&self, unmasked: bool);
+}
+
+static mut INTERRUPT_MASK: Option<&'static dyn InterruptMask> = None;
+
+/// Set the interrupt mask used by `InterruptMasking`.
+///
+/// # Safety
+///
+/// The mask is read without synchronization by every critical section. This must be
+/// called before any task is started, any interrupt is enabled or any other thread is
+/// spawned, and never while another context may be in a critical section.
+///
+/// # Arguments
+///
+/// * `mask` - The interrupt mask to use.
+pub unsafe fn set_interrupt_mask(mask: &'static dyn InterruptMask) {
+    INTERRUPT_MASK = Some(mask);
+}
+
+/// Interrupt mask simulated by a global, reentrant lock, for running `InterruptMasking`
+/// on a host.
+///
+/// The thread disabling "interrupts" holds the lock until it restores them, so all other
+/// threads are excluded like interrupt handlers on a single core. This is the mask used
+/// by `InterruptMasking` with the `std` feature when no other mask is set.
+///
+/// ## Example
+///
+/// ```
+/// use uio::critical_section::{InterruptMask, StdInterruptMask};
+///
+/// static MASK: StdInterruptMask = StdInterruptMask::new();
+///
+/// fn main() {
+///     let unmasked = MASK.disable();
+///     assert!(unmasked);
+///     // Nested sections on the same thread don't wait, and don't unmask.
+///     assert!(!MASK.disable());
+///     MASK.restore(false);
+///     let other = std::thread::spawn(|| {
+///         let unmasked = MASK.disable();
+///         MASK.restore(unmasked);
+///     });
+///     std::thread::sleep(std::time::Duration::from_millis(10));
+///     assert!(!other.is_finished());
+///     MASK.restore(unmasked);
+///     other.join().unwrap();
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub struct StdInterruptMask {
+    owner: std::sync::Mutex<Option<std::thread::ThreadId>>,
+    released: std::sync::Condvar,
+}
+
+#[cfg(feature = "std")]
+impl StdInterruptMask {
+    pub const fn new() -> Self {
+        Self {
+            owner: std::sync::Mutex::new(None),
+            released: std::sync::Condvar::new(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for StdInterruptMask {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl InterruptMask for StdInterruptMask {
+    fn disable(&self) -> bool {
+        let current = std::thread::current().id();
+        let mut owner = self.owner.lock().unwrap_or_else(PoisonError::into_inner);
+        if *owner == Some(current) {
+            return false;
+        }
+        while owner.is_some() {
+            owner = self
+                .released
+                .wait(owner)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        *owner = Some(current);
+        true
+    }
+
+    fn restore(&self, unmasked: bool) {
+        if unmasked {
+            *self.owner.lock().unwrap_or_else(PoisonError::into_inner) = None;
+            self.released.notify_one();
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+static STD_INTERRUPT_MASK: StdInterruptMask = StdInterruptMask::new();
+
+/// Critical sections masking all interrupts, for single core targets.
+///
+/// The mask is set with `set_interrupt_mask()`. With the `std` feature, and no mask set,
+/// a `StdInterruptMask` is used.
+pub struct InterruptMasking;
+
+impl InterruptMasking {
+    fn mask() -> &'static dyn InterruptMask {
+        match unsafe { INTERRUPT_MASK } {
+            Some(mask) => mask,
+            #[cfg(feature = "std")]
+            None => &STD_INTERRUPT_MASK,
+            #[cfg(not(feature = "std"))]
+            None => {
+                panic!("No interrupt mask set, call critical_section::set_interrupt_mask first")
+            }
+        }
+    }
+}
+
+impl CriticalSection for InterruptMasking {
+    fn with<R>(_lock: &AtomicBool, f: impl FnOnce() -> R) -> R {
+        struct Restore(&'static dyn InterruptMask, bool);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                self.0.restore(self.1);
+            }
+        }
+
+        let mask = Self::mask();
+        let _restore = Restore(mask, mask.disable());
+        f()
+    }
+}
+
+#[cfg(feature = "std")]
+static STD_MUTEX: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static STD_MUTEX_DEPTH: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+}
+
+/// Critical sections holding a global, reentrant, `std::sync::Mutex`.
+#[cfg(feature = "std")]
+pub struct StdMutex;
+
+#[cfg(feature = "std")]
+impl CriticalSection for StdMutex {
+    fn with<R>(_lock: &AtomicBool, f: impl FnOnce() -> R) -> R {
+        struct Depth;
+
+        impl Drop for Depth {
+            fn drop(&mut self) {
+                STD_MUTEX_DEPTH.with(|depth| depth.set(depth.get() - 1));
+            }
+        }
+
+        let _guard = if STD_MUTEX_DEPTH.with(|depth| depth.get()) == 0 {
+            Some(STD_MUTEX.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+        } else {
+            None
+        };
+        STD_MUTEX_DEPTH.with(|depth| depth.set(depth.get() + 1));
+        let _depth = Depth;
+        f()
+    }
+}
+
+#[cfg(feature = "std-critical-section")]
+pub(crate) type Selected = StdMutex;
+#[cfg(all(
+    not(feature = "std-critical-section"),
+    any(
+        feature = "interrupt-critical-section",
+        all(target_os = "none", not(feature = "spin-critical-section"))
+    )
+))]
+pub(crate) type Selected = InterruptMasking;
+#[cfg(all(
+    not(any(feature = "interrupt-critical-section", feature = "std-critical-section")),
+    any(feature = "spin-critical-section", not(target_os = "none"))
+))]
+pub(crate) type Selected = Atomic;
+
+/// Atomic types that can be updated with `update()` and `store()`.
+pub(crate) trait AtomicValue {
+    type Value: Copy;
+
+    #[cfg(any(feature = "interrupt-critical-section", feature = "std-critical-section", not(target_has_atomic = "8")))]
+    fn load(&self) -> Self::Value;
+
+    fn store(&self, value: Self::Value);
+
+    #[cfg(not(any(feature = "interrupt-critical-section", feature = "std-critical-section", not(target_has_atomic = "8"))))]
+    fn fetch_update(&self, f: impl FnMut(Self::Value) -> Self::Value) -> Self::Value;
+}
+
+macro_rules! impl_atomic_value {
+    ($(impl<$($generic:ident),*> $atomic:ty => $value:ty;)*) => {
+        $(
+            impl<$($generic),*> AtomicValue for $atomic {
+                type Value = $value;
+
+                #[cfg(any(feature = "interrupt-critical-section", feature = "std-critical-section", not(target_has_atomic = "8")))]
+                fn load(&self) -> Self::Value {
+                    self.load(Ordering::Acquire)
+                }
+
+                fn store(&self, value: Self::Value) {
+                    self.store(value, Ordering::Release)
+                }
+
+                #[cfg(not(any(feature = "interrupt-critical-section", feature = "std-critical-section", not(target_has_atomic = "8"))))]
+                fn fetch_update(&self, mut f: impl FnMut(Self::Value) -> Self::Value) -> Self::Value {
+                    match self.fetch_update(Ordering::AcqRel, Ordering::Acquire, |value| Some(f(value))) {
+                        Ok(previous) | Err(previous) => previous,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_atomic_value! {
+    impl<> AtomicBool => bool;
+    impl<> AtomicU8 => u8;
+    impl<> AtomicUsize => usize;
+    impl<T> AtomicPtr<T> => *mut T;
+}
+
+/// Flag passed to critical sections that only protect a single read-modify-write, these
+/// are only used by implementations that ignore the flag.
+#[cfg(any(feature = "interrupt-critical-section", feature = "std-critical-section", not(target_has_atomic = "8")))]
+static UPDATE_LOCK: AtomicBool = AtomicBool::new(false);
+
+/// Atomically replace the value of `atomic` with `f(value)`, returning the previous value.
+///
+/// `f` may be called several times.
+pub(crate) fn update<A: AtomicValue>(atomic: &A, mut f: impl FnMut(A::Value) -> A::Value) -> A::Value {
+    #[cfg(any(feature = "interrupt-critical-section", feature = "std-critical-section", not(target_has_atomic = "8")))]
+    {
+        Selected::with(&UPDATE_LOCK, || {
+            let previous = atomic.load();
+            atomic.store(f(previous));
+            previous
+        })
+    }
+    #[cfg(not(any(feature = "interrupt-critical-section", feature = "std-critical-section", not(target_has_atomic = "8"))))]
+    {
+        atomic.fetch_update(&mut f)
+    }
+}
+
+/// Atomically replace the value of `atomic` with `value`, returning the previous value.
+pub(crate) fn swap<A: AtomicValue>(atomic: &A, value: A::Value) -> A::Value {
+    update(atomic, |_| value)
+}
+
+/// Store `value` in `atomic`.
+///
+/// Without native compare-and-swap `update()` is a load and a store inside a critical
+/// section, and a plain store landing in between would be lost. Atomics that are ever
+/// updated must only be stored through this.
+pub(crate) fn store<A: AtomicValue>(atomic: &A, value: A::Value) {
+    #[cfg(any(feature = "interrupt-critical-section", feature = "std-critical-section", not(target_has_atomic = "8")))]
+    Selected::with(&UPDATE_LOCK, || atomic.store(value));
+    #[cfg(not(any(feature = "interrupt-critical-section", feature = "std-critical-section", not(target_has_atomic = "8"))))]
+    atomic.store(value);
+}
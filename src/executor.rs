@@ -1,36 +1,90 @@
-use core::sync::atomic::{AtomicPtr, Ordering};
-use core::task::{Context, RawWaker, RawWakerVTable, Waker};
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
 
 use core::pin::Pin;
 
-use embedded_async::intrusive::intrusive_list;
+use crate::critical_section::{store, swap, update};
+use crate::ready_queue::{ReadyBatch, ReadyQueue};
 use crate::task::TaskWaker;
 
-static mut CURRENT_TASK_FLAG: AtomicPtr<crate::task::TaskWaker> = AtomicPtr::new(core::ptr::null_mut());
-static mut TASK_LIST: intrusive_list::List<*mut dyn Task> = intrusive_list::List::new();
+pub mod idle;
+mod interrupt_executor;
 
-fn task_list() -> &'static mut intrusive_list::List<*mut dyn Task> {
-    unsafe {
-        &mut  TASK_LIST
-    }
-}
+pub use idle::IdleStrategy;
+pub use interrupt_executor::{InterruptExecutor, Pender};
+#[cfg(feature = "std")]
+pub use interrupt_executor::ThreadInterrupt;
+
+/// Number of task priority levels supported by an executor.
+pub const PRIORITY_LEVELS: usize = 8;
+
+static DEFAULT_EXECUTOR: Executor = Executor::new();
 
 /// Trait for all tasks used by the executor.
-pub trait Task {
+///
+/// Cancelling a task must drop its future in place and resolve its value to `Err(Cancelled)`.
+///
+/// # Safety
+///
+/// Executors keep a pointer to a started task until it finishes. Implementations must be
+/// `!Unpin`, and dropping a task that is still running must cancel it and remove it from
+/// its executor, waiting for a running poll to finish, like `task::Task` does.
+pub unsafe trait Task: crate::future::Cancelable {
     /// Access to the waker
     fn waker(&self) -> &'static TaskWaker;
-    /// Access to the intrusive list node
-    fn node(&mut self) -> &mut intrusive_list::Node<*mut dyn Task>;
+    /// The priority of the task, higher priority tasks are polled first.
+    fn priority(&self) -> u8 {
+        0
+    }
     /// Poll the task. This will normally delegate to some stored futures poll function.
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> core::task::Poll<()>;
 }
 
-pub struct TaskResult<T> {
-    value: *mut crate::future::Value<T>,
+/// Error returned when joining a task that was cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+/// The result of a started task, used to join or abort the task.
+///
+/// The result borrows the task it was created from, so it can't outlive the task:
+///
+/// ```compile_fail,E0515
+/// async fn answer() -> i32 {
+///     42
+/// }
+///
+/// fn start_answer() -> uio::executor::TaskResult<'static, i32> {
+///     uio::task_start!(task, answer());
+///     task
+/// }
+/// ```
+///
+/// ```compile_fail,E0716
+/// async fn answer() -> i32 {
+///     42
+/// }
+///
+/// fn main() {
+///     let result = {
+///         uio::task_start!(task, answer());
+///         task
+///     };
+///     uio::executor::run_until(result).unwrap();
+/// }
+/// ```
+pub struct TaskResult<'a, T> {
+    value: *mut crate::future::Value<Result<T, Cancelled>>,
+    waker: &'static TaskWaker,
+    _task: PhantomData<&'a mut crate::future::Value<Result<T, Cancelled>>>,
 }
 
-impl<T: Unpin> TaskResult<T> {
-    pub async fn join(self) -> T {
+impl<'a, T: Unpin> TaskResult<'a, T> {
+    /// Wait for the task to finish, returning its output or `Err(Cancelled)` if the task
+    /// was aborted.
+    pub async fn join(self) -> Result<T, Cancelled> {
         unsafe {
             let value = &mut *self.value;
             value.await
@@ -38,109 +92,768 @@ impl<T: Unpin> TaskResult<T> {
     }
 }
 
-pub trait TypedTask: Task {
+impl<'a, T> TaskResult<'a, T> {
+    /// Abort the task.
+    ///
+    /// The task is cancelled the next time its executor would poll it: it is removed from
+    /// the executor, its future is dropped in place and `join()` resolves to `Err(Cancelled)`.
+    /// Aborting a task that has already finished has no effect.
+    ///
+    /// This can be called from other threads and interrupts.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use uio::executor::{yield_now, Cancelled};
+    ///
+    /// async fn forever() {
+    ///     loop {
+    ///         yield_now().await;
+    ///     }
+    /// }
+    ///
+    /// async fn supervisor() {
+    ///     uio::task_start!(worker, forever());
+    ///     yield_now().await;
+    ///     worker.abort();
+    ///     assert_eq!(worker.join().await, Err(Cancelled));
+    /// }
+    ///
+    /// fn main() {
+    ///     uio::task_start!(main_task, supervisor());
+    ///     uio::executor::run();
+    /// }
+    /// ```
+    pub fn abort(&self) {
+        if self.waker.request_cancel() {
+            if let Some(executor) = self.waker.executor() {
+                executor.wake_task(self.waker);
+            }
+        }
+    }
+}
+
+/// Trait for tasks with an output.
+///
+/// # Safety
+///
+/// `value_ptr` must point into the task, and be valid for as long as the task is.
+pub unsafe trait TypedTask: Task {
     type Output;
 
-    fn value_ptr(&mut self) -> *mut crate::future::Value<Self::Output>;
+    fn value_ptr(&mut self) -> *mut crate::future::Value<Result<Self::Output, Cancelled>>;
 }
 
-#[derive(PartialOrd, PartialEq)]
-enum TaskState {
-    NotReady,
-    Pending,
-    Finished,
+/// An executor owning its own set of tasks.
+///
+/// Each executor keeps track of the tasks started on it, and only polls tasks woken
+/// through their own wakers. Several executors can be used at once, for instance one per
+/// thread in host tests, but a task is only ever run by the executor it was started on.
+///
+/// The ready task with the highest priority is always polled first, tasks with equal
+/// priority are polled in a round-robin fashion.
+///
+/// The free functions `run()`, `start()` and `set_idle_strategy()` all operate on a default
+/// executor instance.
+///
+/// ## Example
+///
+/// ```
+/// use uio::executor::Executor;
+///
+/// static EXECUTOR: Executor = Executor::new();
+///
+/// async fn answer() -> i32 {
+///     42
+/// }
+///
+/// fn main() {
+///     let thread = std::thread::spawn(|| {
+///         uio::task_decl!(task, answer());
+///         let _ = EXECUTOR.start(task);
+///         EXECUTOR.run();
+///     });
+///
+///     uio::task_start!(main_task, answer());
+///     uio::executor::run();
+///     thread.join().unwrap();
+/// }
+/// ```
+pub struct Executor {
+    ready_queues: [ReadyQueue; PRIORITY_LEVELS],
+    ready_batches: UnsafeCell<[ReadyBatch; PRIORITY_LEVELS]>,
+    starved_polls: AtomicUsize,
+    live_tasks: AtomicUsize,
+    current_task: AtomicPtr<TaskWaker>,
+    idle_strategy: UnsafeCell<&'static dyn IdleStrategy>,
+    pender: Option<&'static dyn Pender>,
+    running: AtomicBool,
+    root_waker: TaskWaker,
+    /// Set once the executor is linked into `EXECUTORS`.
+    listed: AtomicBool,
+    next_listed: AtomicPtr<Executor>,
 }
 
-fn maybe_poll_task(task: &mut (dyn Task + 'static)) -> TaskState {
-    let waker = task.waker();
-    if waker.is_ready_to_poll() {
-        waker.clear_ready_to_poll();
-        set_current_task_flag(waker);
+/// Every executor that has run, linked through `Executor::next_listed`, for signalling
+/// them when the nearest timer deadline changes.
+static EXECUTORS: AtomicPtr<Executor> = AtomicPtr::new(core::ptr::null_mut());
+
+unsafe impl Sync for Executor {}
+
+impl Executor {
+    /// Create a new executor using the busy-spinning idle strategy.
+    pub const fn new() -> Self {
+        Self::with_idle_strategy(&idle::Spin)
+    }
 
-        let task = unsafe { core::pin::Pin::new_unchecked(task) };
-        let waker = make_waker_for_current();
-        let mut context = Context::from_waker(&waker);
-        match task.poll(&mut context) {
-            core::task::Poll::Ready(_) => TaskState::Finished,
-            _ => TaskState::Pending,
+    /// Create a new executor using the specified idle strategy.
+    ///
+    /// # Arguments
+    ///
+    /// * `strategy` - The idle strategy to use.
+    pub const fn with_idle_strategy(strategy: &'static dyn IdleStrategy) -> Self {
+        Self::with_wake_hooks(strategy, None)
+    }
+
+    const fn with_wake_hooks(strategy: &'static dyn IdleStrategy, pender: Option<&'static dyn Pender>) -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY_QUEUE: ReadyQueue = ReadyQueue::new();
+        const EMPTY_BATCH: ReadyBatch = ReadyBatch::empty();
+        Self {
+            ready_queues: [EMPTY_QUEUE; PRIORITY_LEVELS],
+            ready_batches: UnsafeCell::new([EMPTY_BATCH; PRIORITY_LEVELS]),
+            starved_polls: AtomicUsize::new(0),
+            live_tasks: AtomicUsize::new(0),
+            current_task: AtomicPtr::new(core::ptr::null_mut()),
+            idle_strategy: UnsafeCell::new(strategy),
+            pender,
+            running: AtomicBool::new(false),
+            root_waker: TaskWaker::new(),
+            listed: AtomicBool::new(false),
+            next_listed: AtomicPtr::new(core::ptr::null_mut()),
         }
-    } else {
-        TaskState::NotReady
     }
-}
 
-/// Runs the executor until all started and will-be-started tasks have finished.
-///
-/// This is typically only called once at the end of main.
-///
-/// # Panics
-///
-/// Any nested calls will cause a panic.
-pub fn run() {
-    static mut TAKEN: bool = false;
-    unsafe {
-        if TAKEN {
+    /// Set the strategy used by `run()` when no task is ready to be polled.
+    ///
+    /// # Safety
+    ///
+    /// Waking a task reads the strategy from any thread or interrupt, so this must be
+    /// called before any task is started on the executor, and never while it is running.
+    ///
+    /// # Arguments
+    ///
+    /// * `strategy` - The idle strategy to use.
+    pub unsafe fn set_idle_strategy(&self, strategy: &'static dyn IdleStrategy) {
+        *self.idle_strategy.get() = strategy;
+    }
+
+    fn idle_strategy(&self) -> &'static dyn IdleStrategy {
+        unsafe { *self.idle_strategy.get() }
+    }
+
+    /// The number of times a task was polled while lower priority tasks were ready.
+    ///
+    /// A counter that keeps increasing means that higher priority tasks are starving
+    /// lower priority tasks of execution time.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use std::sync::Mutex;
+    /// use uio::executor::{yield_now, Executor};
+    ///
+    /// static EXECUTOR: Executor = Executor::new();
+    /// static LOG: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+    ///
+    /// async fn worker(name: &'static str, yields: usize) {
+    ///     for _ in 0..yields {
+    ///         LOG.lock().unwrap().push(name);
+    ///         yield_now().await;
+    ///     }
+    ///     LOG.lock().unwrap().push(name);
+    /// }
+    ///
+    /// fn main() {
+    ///     uio::task_decl!(logger, worker("low", 0));
+    ///     uio::task_decl!(control1, worker("high1", 2), priority = 2);
+    ///     uio::task_decl!(control2, worker("high2", 2), priority = 2);
+    ///     let _ = EXECUTOR.start(logger);
+    ///     let _ = EXECUTOR.start(control1);
+    ///     let _ = EXECUTOR.start(control2);
+    ///     EXECUTOR.run();
+    ///
+    ///     // Equal priorities take turns, the ready low priority task waits for both.
+    ///     assert_eq!(*LOG.lock().unwrap(), ["high1", "high2", "high1", "high2", "high1", "high2", "low"]);
+    ///     assert_eq!(EXECUTOR.starved_polls(), 6);
+    /// }
+    /// ```
+    pub fn starved_polls(&self) -> usize {
+        self.starved_polls.load(Ordering::Acquire)
+    }
+
+    /// Start a task, scheduling it to be run by this executor.
+    ///
+    /// This can be called both before `run()` and within async functions. The task may be
+    /// polled for as long as the executor runs, so it can't borrow anything that isn't
+    /// `'static`, use `scope()` to start tasks that do:
+    ///
+    /// ```compile_fail,E0597
+    /// async fn print(text: &str) {
+    ///     println!("{}", text);
+    /// }
+    ///
+    /// fn main() {
+    ///     let text = String::from("hello");
+    ///     uio::task_start!(task, print(&text));
+    ///     uio::executor::run();
+    /// }
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `task` - The task to start.
+    pub fn start<'a, T: Task + TypedTask + 'static>(&'static self, task: Pin<&'a mut T>) -> TaskResult<'a, T::Output> {
+        // The task borrows nothing, and the pinned task is removed from the executor before
+        // its memory is reused.
+        unsafe { self.start_unchecked(task) }
+    }
+
+    /// Start a task without requiring it to be `'static`.
+    ///
+    /// # Safety
+    ///
+    /// The task must have finished before anything it borrows goes out of scope.
+    unsafe fn start_unchecked<'a, T: Task + TypedTask + 'a>(&'static self, task: Pin<&'a mut T>) -> TaskResult<'a, T::Output> {
+        let task = task.get_unchecked_mut();
+        let waker = task.waker();
+        let priority = task.priority().min((PRIORITY_LEVELS - 1) as u8);
+        // The task is pinned, and dropping it while it is running removes it from the
+        // executor, so the executor never uses the pointer after the task is gone.
+        let task_ptr = core::mem::transmute::<*mut (dyn Task + 'a), *mut (dyn Task + 'static)>(task as *mut T);
+        waker.set_task(task_ptr, priority, self);
+        waker.set_started();
+        update(&self.live_tasks, |tasks| tasks + 1);
+        self.wake_task(waker);
+
+        TaskResult {
+            value: task.value_ptr(),
+            waker,
+            _task: PhantomData,
+        }
+    }
+
+    /// Runs the executor until all started and will-be-started tasks have finished.
+    ///
+    /// Only tasks that have been woken are polled. When no task is ready the configured
+    /// idle strategy is used to wait for the next wake or timer deadline.
+    ///
+    /// # Panics
+    ///
+    /// Any nested calls will cause a panic.
+    pub fn run(&'static self) {
+        self.run_while(|| self.live_tasks.load(Ordering::Acquire) > 0);
+    }
+
+    /// Runs the executor until `future` completes, returning its output.
+    ///
+    /// All started tasks are driven while waiting for `future`. Tasks that haven't finished
+    /// when `future` completes are left on the executor for a later call to `run()`,
+    /// `block_on()` or `run_until()`.
+    ///
+    /// # Panics
+    ///
+    /// Calling this from within a task running on the same executor will cause a panic.
+    ///
+    /// # Arguments
+    ///
+    /// * `future` - The future to drive to completion.
+    pub fn block_on<F: Future>(&'static self, future: F) -> F::Output {
+        if self.running.load(Ordering::Acquire) {
             panic!("Nested calls to run not supported");
         }
-        TAKEN = true;
+        let task = crate::task::Task::new(future, &self.root_waker);
+        crate::pin_utils::pin_mut!(task);
+        // Returns only once the task has finished, and the task is cancelled when dropped
+        // while unwinding.
+        unsafe { self.start_unchecked(task.as_mut()) };
+        self.run_while(|| !self.root_waker.is_finished());
+        task.take_output().expect("Root task finished without an output")
     }
-    'main_loop: loop {
-        unsafe {
-            let mut available_tasks = intrusive_list::List::new();
-            task_list().move_to_front_of(&mut available_tasks);
-            let task_list = task_list();
-            while let Some(next_task) = available_tasks.pop_front() {
-                let task = &mut **next_task.owner_mut().expect("");
-                match maybe_poll_task(task) {
-                    TaskState::NotReady | TaskState::Pending => {
-                        let next_task_ptr = next_task as *mut intrusive_list::Link<*mut dyn Task>;
-                        let owner = next_task.owner_mut().expect("");
-                        task_list.push_link_back(owner, next_task_ptr);
-                    },
-                    TaskState::Finished => task.waker().set_finished(),
+
+    /// Run `f`, which can start tasks borrowing from the environment through the `Scope`,
+    /// then run the executor until all those tasks have finished.
+    ///
+    /// The tasks must be declared outside of `f`, so they outlive the scope. Other tasks on
+    /// the executor are run as well while waiting.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use uio::sync::Mutex;
+    ///
+    /// async fn add(total: &Mutex<u32>, value: u32) {
+    ///     *total.lock().await += value;
+    /// }
+    ///
+    /// fn main() {
+    ///     let total = Mutex::new(0);
+    ///     uio::task_decl!(first, add(&total, 1));
+    ///     uio::task_decl!(second, add(&total, 2));
+    ///     uio::executor::scope(|s| {
+    ///         s.start(first);
+    ///         s.start(second);
+    ///     });
+    ///     assert_eq!(*total.try_lock().unwrap(), 3);
+    /// }
+    /// ```
+    ///
+    /// Tasks declared inside the scope don't live long enough:
+    ///
+    /// ```compile_fail,E0597
+    /// async fn answer() -> i32 {
+    ///     42
+    /// }
+    ///
+    /// fn main() {
+    ///     uio::executor::scope(|s| {
+    ///         uio::task_decl!(task, answer());
+    ///         s.start(task);
+    ///     });
+    /// }
+    /// ```
+    ///
+    /// The scope also waits when another thread starts running the executor meanwhile:
+    ///
+    /// ```
+    /// use core::sync::atomic::{AtomicBool, Ordering};
+    /// use uio::executor::{yield_now, Executor};
+    ///
+    /// static EXECUTOR: Executor = Executor::new();
+    ///
+    /// async fn wait_for(started: &AtomicBool, done: &AtomicBool) {
+    ///     started.store(true, Ordering::Release);
+    ///     while !done.load(Ordering::Acquire) {
+    ///         yield_now().await;
+    ///     }
+    /// }
+    ///
+    /// fn main() {
+    ///     let started = AtomicBool::new(false);
+    ///     let done = AtomicBool::new(false);
+    ///     std::thread::scope(|threads| {
+    ///         uio::task_decl!(task, wait_for(&started, &done));
+    ///         EXECUTOR.scope(|s| {
+    ///             s.start(task);
+    ///             threads.spawn(|| EXECUTOR.run());
+    ///             while !started.load(Ordering::Acquire) {
+    ///                 std::thread::yield_now();
+    ///             }
+    ///             threads.spawn(|| {
+    ///                 std::thread::sleep(std::time::Duration::from_millis(10));
+    ///                 done.store(true, Ordering::Release);
+    ///             });
+    ///         });
+    ///         assert!(done.load(Ordering::Acquire));
+    ///     });
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics, before running `f`, if the executor is already running, either on another
+    /// thread or because this is called from within one of its tasks:
+    ///
+    /// ```should_panic
+    /// uio::executor::block_on(async {
+    ///     uio::executor::scope(|_s| {});
+    /// });
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Function starting the scoped tasks.
+    pub fn scope<'env, R>(&'static self, f: impl for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R) -> R {
+        // Waiting for the scoped tasks from within one of the executor's tasks would never
+        // end, since that task is not finished before the scope is.
+        if self.running.load(Ordering::Acquire) {
+            panic!("Scope on an executor that is already running");
+        }
+        // Dropped after the scope, even while unwinding, running the executor until the
+        // tasks have finished.
+        let tasks = ScopeTasks {
+            executor: self,
+            live_tasks: AtomicUsize::new(0),
+        };
+        let scope = Scope {
+            tasks: &tasks,
+            _scope: PhantomData,
+            _env: PhantomData,
+        };
+        f(&scope)
+    }
+
+    /// Runs the executor until the task behind `result` has finished, returning its output.
+    ///
+    /// See `block_on()` for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - The result of a task started on this executor.
+    pub fn run_until<T: Unpin>(&'static self, result: TaskResult<'_, T>) -> Result<T, Cancelled> {
+        self.block_on(result.join())
+    }
+
+    fn run_while(&'static self, keep_running: impl Fn() -> bool) {
+        if !self.try_run_while(keep_running) {
+            panic!("Nested calls to run not supported");
+        }
+    }
+
+    /// Like `run_while()`, but returns `false` instead of running if the executor is
+    /// already running.
+    fn try_run_while(&'static self, keep_running: impl Fn() -> bool) -> bool {
+        if swap(&self.running, true) {
+            return false;
+        }
+        self.list();
+        while keep_running() {
+            crate::time::expire_timers();
+            if !self.poll_next_task() && keep_running() {
+                self.idle();
+            }
+        }
+        store(&self.running, false);
+        true
+    }
+
+    /// Link the executor into `EXECUTORS`, if it isn't already.
+    fn list(&'static self) {
+        if !swap(&self.listed, true) {
+            let executor = self as *const Executor as *mut Executor;
+            update(&EXECUTORS, |head| {
+                self.next_listed.store(head, Ordering::Relaxed);
+                executor
+            });
+        }
+    }
+
+    /// Sleep until the next timer deadline, or until a task is woken.
+    ///
+    /// A timer inserted as the new head of the timer queue afterwards, by another thread or
+    /// an interrupt, signals the idle strategy through `signal_deadline_changed()`. The
+    /// executor is running and listed before the deadline is read under the timer queue
+    /// lock, so the inserting context either is seen here or sees this executor.
+    fn idle(&self) {
+        let deadline = crate::time::next_deadline();
+        if let Some(deadline) = deadline {
+            if crate::time::advance_clock_to(deadline) {
+                return;
+            }
+        }
+        let strategy = self.idle_strategy();
+        strategy.pre_sleep(deadline);
+        strategy.sleep_until(deadline);
+        strategy.post_wake();
+    }
+
+    /// Poll ready tasks until no task is ready, then return.
+    fn poll_ready_tasks(&'static self) {
+        if swap(&self.running, true) {
+            panic!("Nested calls to run not supported");
+        }
+        crate::time::expire_timers();
+        while self.poll_next_task() {}
+        store(&self.running, false);
+    }
+
+    /// Poll the ready task with the highest priority.
+    ///
+    /// Returns `false` if no task was ready.
+    fn poll_next_task(&self) -> bool {
+        for priority in (0..PRIORITY_LEVELS).rev() {
+            let next_waker = {
+                let batch = unsafe { &mut (*self.ready_batches.get())[priority] };
+                if batch.is_empty() {
+                    *batch = self.ready_queues[priority].take_all();
+                }
+                batch.next()
+            };
+            if let Some(waker) = next_waker {
+                if self.has_ready_tasks_below(priority) {
+                    update(&self.starved_polls, |polls| polls + 1);
                 }
+                self.poll_woken_task(waker);
+                return true;
             }
         }
-        if task_list().is_empty() {
-            break 'main_loop;
+        false
+    }
+
+    fn has_ready_tasks_below(&self, priority: usize) -> bool {
+        let batches = unsafe { &*self.ready_batches.get() };
+        (0..priority).any(|lower| !batches[lower].is_empty() || !self.ready_queues[lower].is_empty())
+    }
+
+    fn poll_woken_task(&self, waker: &'static TaskWaker) {
+        waker.clear_ready_to_poll();
+        let Some(_claim) = waker.try_claim() else {
+            // The task is being dropped, which removes it from the executor.
+            return;
+        };
+        // Woken after it finished, the wake is stale.
+        if !waker.is_finished() {
+            self.poll_claimed_task(waker);
+        }
+    }
+
+    fn poll_claimed_task(&self, waker: &'static TaskWaker) {
+        let task = waker.task().expect("Running task without a task pointer");
+        let task = unsafe { &mut *task.as_ptr() };
+        if waker.is_cancel_requested() {
+            task.cancel_future();
+            self.finish_task(waker);
+            return;
+        }
+        self.set_current_task(waker);
+
+        let task = unsafe { Pin::new_unchecked(task) };
+        let waker_for_current = make_waker(self.current_task());
+        let mut context = Context::from_waker(&waker_for_current);
+        if task.poll(&mut context).is_ready() {
+            self.finish_task(waker);
+        }
+    }
+
+    pub(crate) fn finish_task(&self, waker: &'static TaskWaker) {
+        waker.set_finished();
+        update(&self.live_tasks, |tasks| tasks - 1);
+        if let Some(scope_tasks) = waker.take_scope() {
+            update(scope_tasks, |tasks| tasks - 1);
         }
     }
-    unsafe {
-        TAKEN = false;
+
+    fn set_current_task(&self, waker: &'static TaskWaker) {
+        self.current_task
+            .store(waker as *const TaskWaker as *mut TaskWaker, Ordering::Release);
     }
+
+    fn current_task(&self) -> &'static TaskWaker {
+        unsafe { &*self.current_task.load(Ordering::Acquire) }
+    }
+
+    fn wake_task(&self, waker: &'static TaskWaker) {
+        if waker.set_ready_to_poll() {
+            self.ready_queues[waker.priority()].push(waker);
+        }
+        self.idle_strategy().wake_signal();
+        if let Some(pender) = self.pender {
+            pender.pend();
+        }
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Signal the idle strategy of every running executor, after a timer was inserted ahead of
+/// the deadline they may be sleeping until.
+///
+/// The strategies return from `sleep_until()` and the executors go idle again with the
+/// new deadline, calling `pre_sleep()` with it, which re-programs an alarm.
+pub(crate) fn signal_deadline_changed() {
+    let mut next = EXECUTORS.load(Ordering::Acquire);
+    while let Some(executor) = unsafe { next.as_ref() } {
+        if executor.running.load(Ordering::Acquire) {
+            executor.idle_strategy().wake_signal();
+        }
+        next = executor.next_listed.load(Ordering::Relaxed);
+    }
+}
+
+/// A scope for starting tasks that borrow from the environment, see `Executor::scope`.
+pub struct Scope<'scope, 'env: 'scope> {
+    tasks: &'scope ScopeTasks,
+    _scope: PhantomData<&'scope mut &'scope ()>,
+    _env: PhantomData<&'env mut &'env ()>,
+}
+
+/// The tasks started in a `Scope`, waited for when dropped.
+struct ScopeTasks {
+    executor: &'static Executor,
+    live_tasks: AtomicUsize,
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// Start a task on the executor of the scope.
+    ///
+    /// # Arguments
+    ///
+    /// * `task` - The task to start.
+    pub fn start<T: Task + TypedTask + 'env>(&'scope self, task: Pin<&'scope mut T>) -> TaskResult<'scope, T::Output> {
+        task.waker().set_scope(&self.tasks.live_tasks);
+        update(&self.tasks.live_tasks, |tasks| tasks + 1);
+        // The scope doesn't end before the task has finished.
+        unsafe { self.tasks.executor.start_unchecked(task) }
+    }
+}
+
+impl Drop for ScopeTasks {
+    fn drop(&mut self) {
+        // The tasks borrow from the environment, so this must not return before they have
+        // finished, and must not panic. When the executor was started on another thread
+        // after the scope, wait for that thread to finish the tasks, or to stop running so
+        // this can take over.
+        let live = || self.live_tasks.load(Ordering::Acquire) > 0;
+        while live() {
+            if !self.executor.try_run_while(live) {
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
+
+/// Set the strategy used by `run()` when no task is ready to be polled.
+///
+/// The default strategy busy-spins.
+///
+/// # Safety
+///
+/// This must be called before any task is started on the default executor, see
+/// `Executor::set_idle_strategy`.
+///
+/// # Arguments
+///
+/// * `strategy` - The idle strategy to use.
+pub unsafe fn set_idle_strategy(strategy: &'static dyn IdleStrategy) {
+    DEFAULT_EXECUTOR.set_idle_strategy(strategy);
 }
 
-/// Start a task, scheduling it to be run.
+/// Runs the default executor until all started and will-be-started tasks have finished.
+///
+/// This is typically only called once at the end of main.
+///
+/// # Panics
+///
+/// Any nested calls will cause a panic.
+pub fn run() {
+    DEFAULT_EXECUTOR.run();
+}
+
+/// Runs the default executor until `future` completes, returning its output.
+///
+/// Tasks that haven't finished when `future` completes are left on the executor for a
+/// later call to `run()`, `block_on()` or `run_until()`.
+///
+/// ## Example
+///
+/// ```
+/// async fn double(value: i32) -> i32 {
+///     value * 2
+/// }
+///
+/// fn main() {
+///     uio::task_start!(task, double(21));
+///     assert_eq!(uio::executor::block_on(task.join()), Ok(42));
+///     assert_eq!(uio::executor::block_on(double(1)), 2);
+/// }
+/// ```
+///
+/// # Panics
+///
+/// Calling this from within a task will cause a panic.
+///
+/// # Arguments
+///
+/// * `future` - The future to drive to completion.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    DEFAULT_EXECUTOR.block_on(future)
+}
+
+/// Runs the default executor until the task behind `result` has finished, returning its output.
+///
+/// See `block_on()` for details.
+///
+/// # Arguments
+///
+/// * `result` - The result of a started task.
+pub fn run_until<T: Unpin>(result: TaskResult<'_, T>) -> Result<T, Cancelled> {
+    DEFAULT_EXECUTOR.run_until(result)
+}
+
+/// Start a task, scheduling it to be run by the default executor.
 ///
 /// This can be called both before `run()` and within async functions.
 ///
 /// # Arguments
 ///
 /// * `task` - The task to start.
-pub fn start<T: Task + TypedTask + 'static>(task: Pin<&mut T>) -> TaskResult<T::Output> {
-    let task = unsafe { task.get_unchecked_mut() };
-    task.waker().set_started();
-    task.waker().set_ready_to_poll();
-    *task.node() = intrusive_list::Node::new(task as *mut _);
-    task_list().push_node_back(task.node());
+pub fn start<'a, T: Task + TypedTask + 'static>(task: Pin<&'a mut T>) -> TaskResult<'a, T::Output> {
+    DEFAULT_EXECUTOR.start(task)
+}
 
-    TaskResult {
-        value: task.value_ptr(),
-    }
+/// Run `f` and then the default executor, until all tasks started through the `Scope`
+/// have finished.
+///
+/// See `Executor::scope` for details.
+///
+/// # Arguments
+///
+/// * `f` - Function starting the scoped tasks.
+pub fn scope<'env, R>(f: impl for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R) -> R {
+    DEFAULT_EXECUTOR.scope(f)
 }
 
-fn set_current_task_flag(waker: &'static TaskWaker) {
-    unsafe {
-        CURRENT_TASK_FLAG.store(waker as *const TaskWaker as *mut TaskWaker, Ordering::Release);
-    }
+/// Future returned by `yield_now()`.
+pub struct YieldNow {
+    yielded: bool,
 }
 
-fn current_task_flag() -> &'static TaskWaker {
-    unsafe { & *CURRENT_TASK_FLAG.load(Ordering::Acquire) }
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.yielded {
+            Poll::Ready(())
+        } else {
+            self.yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
 }
 
-fn make_waker_for_current() -> Waker {
-    make_waker(current_task_flag())
+/// Give up the executor to other ready tasks.
+///
+/// The current task is marked as ready again, and is polled after the tasks that were
+/// already ready when it yielded.
+///
+/// ## Example
+///
+/// ```
+/// use std::sync::Mutex;
+///
+/// static LOG: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+///
+/// async fn worker(id: u32) {
+///     for _ in 0..3 {
+///         LOG.lock().unwrap().push(id);
+///         uio::executor::yield_now().await;
+///     }
+/// }
+///
+/// fn main() {
+///     uio::task_start!(worker1, worker(1));
+///     uio::task_start!(worker2, worker(2));
+///     uio::executor::run();
+///     assert_eq!(*LOG.lock().unwrap(), [1, 2, 1, 2, 1, 2]);
+/// }
+/// ```
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
 }
 
 fn make_waker(flag: &'static TaskWaker) -> Waker {
@@ -153,8 +866,10 @@ fn raw_wake(data: *const ()) {
         return;
     }
 
-    let data = unsafe { &*(data as *const TaskWaker) };
-    data.set_ready_to_poll();
+    let waker = unsafe { &*(data as *const TaskWaker) };
+    if let Some(executor) = waker.executor() {
+        executor.wake_task(waker);
+    }
 }
 
 fn make_raw_waker(data: *const ()) -> RawWaker {
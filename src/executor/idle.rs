@@ -0,0 +1,353 @@
+//! Idle strategies
+//!
+//! The executor calls into an idle strategy whenever no task is ready to be polled.
+//! Waking a task signals the idle strategy, ending the idle period.
+
+use core::sync::atomic::AtomicBool;
+
+use crate::critical_section::{store, swap, CriticalSection, InterruptMasking};
+use crate::time::Instant;
+
+/// Trait for the strategies used by the executor when no task is ready to be polled.
+///
+/// When idle, the executor computes the deadline of the nearest timer and calls
+/// `pre_sleep`, `sleep_until` and `post_wake` in order. Boards can use the deadline to
+/// program a wake-up alarm and enter deep sleep instead of taking a periodic tick interrupt.
+///
+/// ## Example
+///
+/// ```
+/// use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+/// use uio::executor::idle::IdleStrategy;
+/// use uio::time::{Clock, Duration, Instant, Timer};
+///
+/// /// Simulated board, where sleeping jumps straight to the programmed alarm.
+/// struct Board {
+///     now: AtomicU64,
+///     alarm: AtomicU64,
+///     sleeps: AtomicUsize,
+/// }
+///
+/// impl Clock for Board {
+///     fn now(&self) -> Instant {
+///         Instant::from_micros(self.now.load(Ordering::Acquire))
+///     }
+/// }
+///
+/// impl IdleStrategy for Board {
+///     fn pre_sleep(&self, deadline: Option<Instant>) {
+///         let deadline = deadline.expect("Sleeping without a wake-up source");
+///         self.alarm.store(deadline.as_micros(), Ordering::Release);
+///     }
+///
+///     fn sleep_until(&self, _deadline: Option<Instant>) {
+///         self.now.store(self.alarm.load(Ordering::Acquire), Ordering::Release);
+///     }
+///
+///     fn post_wake(&self) {
+///         self.sleeps.fetch_add(1, Ordering::AcqRel);
+///     }
+///
+///     fn wake_signal(&self) {}
+/// }
+///
+/// static BOARD: Board = Board {
+///     now: AtomicU64::new(0),
+///     alarm: AtomicU64::new(0),
+///     sleeps: AtomicUsize::new(0),
+/// };
+///
+/// fn main() {
+///     unsafe { uio::time::set_clock(&BOARD) };
+///     unsafe { uio::executor::set_idle_strategy(&BOARD) };
+///     uio::executor::block_on(async {
+///         Timer::after(Duration::from_millis(30)).await;
+///         assert_eq!(Instant::now(), Instant::from_millis(30));
+///         Timer::after(Duration::from_millis(20)).await;
+///     });
+///     assert_eq!(Instant::now(), Instant::from_millis(50));
+///     assert_eq!(BOARD.sleeps.load(Ordering::Acquire), 2);
+/// }
+/// ```
+pub trait IdleStrategy: Sync {
+    /// Called by the executor before `sleep_until`, with the same deadline.
+    ///
+    /// The default implementation does nothing.
+    fn pre_sleep(&self, _deadline: Option<Instant>) {}
+
+    /// Called by the executor when no task is ready to be polled.
+    ///
+    /// `deadline` is the deadline of the nearest timer, if any timer is pending.
+    /// Implementations may block until `wake_signal` is called or `deadline` is reached,
+    /// and must not return later than `deadline`. Returning early is allowed, the executor
+    /// will simply check for ready tasks and expired timers again.
+    fn sleep_until(&self, deadline: Option<Instant>);
+
+    /// Called by the executor after `sleep_until` has returned.
+    ///
+    /// The default implementation does nothing.
+    fn post_wake(&self) {}
+
+    /// Called every time a task is woken, possibly from another thread or an interrupt.
+    ///
+    /// This must end any ongoing, or the next, call to `sleep_until`.
+    fn wake_signal(&self);
+}
+
+/// Idle strategy that busy-spins, this is the default strategy.
+pub struct Spin;
+
+impl IdleStrategy for Spin {
+    fn sleep_until(&self, _deadline: Option<Instant>) {
+        core::hint::spin_loop();
+    }
+
+    fn wake_signal(&self) {}
+}
+
+/// Idle strategy that calls a target specific wait function, typically an instruction
+/// like `WFI` or `WFE`, when idle.
+///
+/// The wake flag is checked, and the wait function called, with interrupts masked by the
+/// mask of `critical_section::InterruptMasking`. The wait function must return when an
+/// interrupt becomes pending even while interrupts are masked, as `WFI` does. An interrupt
+/// firing after the check then ends the wait, and its handler runs once the mask is
+/// restored, so a wake signalled before the wait function is entered skips the wait.
+/// Timers only expire in time if an alarm interrupt is programmed, see `with_alarm()`.
+///
+/// ## Example
+///
+/// ```
+/// use core::cell::Cell;
+/// use core::sync::atomic::{AtomicBool, Ordering};
+/// use uio::critical_section::{set_interrupt_mask, InterruptMask};
+/// use uio::executor::idle::WaitForInterrupt;
+/// use uio::executor::Executor;
+/// use uio::interrupt::InterruptFlag;
+///
+/// /// Stand-in for the interrupt mask of a single core, held by one thread at a time.
+/// struct Mask(AtomicBool);
+///
+/// thread_local! {
+///     static MASKED: Cell<bool> = Cell::new(false);
+/// }
+///
+/// impl InterruptMask for Mask {
+///     fn disable(&self) -> bool {
+///         if MASKED.with(Cell::get) {
+///             return false;
+///         }
+///         while self.0.swap(true, Ordering::Acquire) {
+///             std::thread::yield_now();
+///         }
+///         MASKED.with(|masked| masked.set(true));
+///         true
+///     }
+///
+///     fn restore(&self, unmasked: bool) {
+///         if unmasked {
+///             MASKED.with(|masked| masked.set(false));
+///             self.0.store(false, Ordering::Release);
+///         }
+///     }
+/// }
+///
+/// static MASK: Mask = Mask(AtomicBool::new(false));
+/// static PENDING: AtomicBool = AtomicBool::new(false);
+/// static IDLE: WaitForInterrupt = WaitForInterrupt::new(wait_for_interrupt);
+/// static EXECUTOR: Executor = Executor::with_idle_strategy(&IDLE);
+/// static READY: InterruptFlag = InterruptFlag::new();
+///
+/// /// Stand-in for `WFI`, returning once an interrupt is pending, even while masked.
+/// fn wait_for_interrupt() {
+///     while !PENDING.load(Ordering::Acquire) {
+///         std::thread::yield_now();
+///     }
+/// }
+///
+/// fn main() {
+///     unsafe { set_interrupt_mask(&MASK) };
+///     // A thread plays the part of the interrupt, pended at once and handled when unmasked.
+///     let interrupt = std::thread::spawn(|| {
+///         std::thread::sleep(std::time::Duration::from_millis(10));
+///         PENDING.store(true, Ordering::Release);
+///         let unmasked = MASK.disable();
+///         PENDING.store(false, Ordering::Release);
+///         READY.signal();
+///         MASK.restore(unmasked);
+///     });
+///     EXECUTOR.block_on(READY.wait());
+///     interrupt.join().unwrap();
+/// }
+/// ```
+pub struct WaitForInterrupt {
+    wait: fn(),
+    alarm: Option<fn(Instant)>,
+    woken: AtomicBool,
+}
+
+impl WaitForInterrupt {
+    /// Create a new strategy, calling `wait` when the executor is idle.
+    pub const fn new(wait: fn()) -> Self {
+        Self {
+            wait,
+            alarm: None,
+            woken: AtomicBool::new(false),
+        }
+    }
+
+    /// Call `alarm` with the deadline of the nearest timer before waiting.
+    ///
+    /// `alarm` must arrange for an interrupt to fire no later than the deadline.
+    /// A timer inserted ahead of the programmed deadline signals the strategy, and the
+    /// executor calls `alarm` again with the new deadline before waiting.
+    pub const fn with_alarm(mut self, alarm: fn(Instant)) -> Self {
+        self.alarm = Some(alarm);
+        self
+    }
+}
+
+impl IdleStrategy for WaitForInterrupt {
+    fn pre_sleep(&self, deadline: Option<Instant>) {
+        if let (Some(alarm), Some(deadline)) = (self.alarm, deadline) {
+            alarm(deadline);
+        }
+    }
+
+    fn sleep_until(&self, _deadline: Option<Instant>) {
+        // `InterruptMasking` ignores the lock, it excludes all other contexts.
+        InterruptMasking::with(&AtomicBool::new(false), || {
+            if !swap(&self.woken, false) {
+                (self.wait)();
+            }
+        });
+    }
+
+    fn wake_signal(&self) {
+        store(&self.woken, true);
+    }
+}
+
+/// Idle strategy that parks the thread running the executor until a task is woken, or
+/// until the deadline of the nearest timer.
+///
+/// The strategy can be moved between threads, waking unparks the thread that went idle
+/// most recently. It must only be used by one running executor at a time.
+///
+/// ## Example
+///
+/// ```
+/// use uio::executor::idle::ThreadPark;
+/// use uio::time::{Duration, Instant, StdClock, Timer};
+///
+/// static CLOCK: StdClock = StdClock::new();
+/// static IDLE: ThreadPark = ThreadPark::new();
+///
+/// fn main() {
+///     unsafe { uio::time::set_clock(&CLOCK) };
+///     unsafe { uio::executor::set_idle_strategy(&IDLE) };
+///     let start = std::time::Instant::now();
+///     uio::executor::block_on(Timer::at(Instant::now() + Duration::from_millis(20)));
+///     let elapsed = start.elapsed();
+///     assert!(elapsed >= std::time::Duration::from_millis(20));
+///     assert!(elapsed < std::time::Duration::from_secs(1));
+/// }
+/// ```
+///
+/// Running the executor from another thread later on still wakes the right thread:
+///
+/// ```
+/// use uio::executor::idle::ThreadPark;
+/// use uio::executor::Executor;
+/// use uio::interrupt::InterruptFlag;
+///
+/// static IDLE: ThreadPark = ThreadPark::new();
+/// static EXECUTOR: Executor = Executor::with_idle_strategy(&IDLE);
+/// static READY: InterruptFlag = InterruptFlag::new();
+///
+/// fn wait_on_new_thread() {
+///     let thread = std::thread::spawn(|| EXECUTOR.block_on(READY.wait()));
+///     std::thread::sleep(std::time::Duration::from_millis(10));
+///     READY.signal();
+///     thread.join().unwrap();
+/// }
+///
+/// fn main() {
+///     wait_on_new_thread();
+///     wait_on_new_thread();
+/// }
+/// ```
+///
+/// A timer started by an interrupt executor while the thread is parked without a deadline
+/// unparks it, to park again until the timer's deadline:
+///
+/// ```
+/// use uio::executor::idle::ThreadPark;
+/// use uio::executor::{InterruptExecutor, ThreadInterrupt};
+/// use uio::time::{Duration, StdClock, Timer};
+///
+/// static CLOCK: StdClock = StdClock::new();
+/// static IDLE: ThreadPark = ThreadPark::new();
+/// static INTERRUPT: ThreadInterrupt = ThreadInterrupt::new(1);
+/// static EXECUTOR: InterruptExecutor = InterruptExecutor::new(&INTERRUPT);
+///
+/// async fn sleeper() {
+///     // Registered while the thread-mode executor is parked without a deadline.
+///     std::thread::sleep(std::time::Duration::from_millis(10));
+///     Timer::after(Duration::from_millis(20)).await;
+/// }
+///
+/// fn main() {
+///     unsafe { uio::time::set_clock(&CLOCK) };
+///     unsafe { uio::executor::set_idle_strategy(&IDLE) };
+///     INTERRUPT.spawn(|| EXECUTOR.on_interrupt());
+///
+///     uio::task_decl!(task, sleeper());
+///     let result = EXECUTOR.start(task);
+///     assert_eq!(uio::executor::run_until(result), Ok(()));
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub struct ThreadPark {
+    thread: std::sync::Mutex<Option<std::thread::Thread>>,
+    woken: AtomicBool,
+}
+
+#[cfg(feature = "std")]
+impl ThreadPark {
+    /// Create a new strategy, parking whichever thread runs the executor.
+    pub const fn new() -> Self {
+        Self {
+            thread: std::sync::Mutex::new(None),
+            woken: AtomicBool::new(false),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for ThreadPark {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl IdleStrategy for ThreadPark {
+    fn sleep_until(&self, deadline: Option<Instant>) {
+        // Recorded on every idle, the executor may be run from a different thread each time.
+        *self.thread.lock().unwrap() = Some(std::thread::current());
+        if !swap(&self.woken, false) {
+            match deadline {
+                Some(deadline) => std::thread::park_timeout(deadline.saturating_duration_since(Instant::now()).into()),
+                None => std::thread::park(),
+            }
+        }
+    }
+
+    fn wake_signal(&self) {
+        store(&self.woken, true);
+        if let Some(thread) = self.thread.lock().unwrap().as_ref() {
+            thread.unpark();
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Executors running in interrupt handlers
+//!
+//! An `InterruptExecutor` polls its tasks from within an interrupt handler. Waking one of
+//! its tasks pends the interrupt, so a higher priority interrupt executor preempts both
+//! lower priority interrupt executors and the thread-mode executor run by `run()`.
+
+use core::pin::Pin;
+
+use super::{Executor, Task, TaskResult, TypedTask};
+
+/// Trait used by an `InterruptExecutor` to request its interrupt.
+pub trait Pender: Sync {
+    /// Pend the interrupt, this is called every time a task of the executor is woken.
+    ///
+    /// This is typically implemented by setting the interrupt pending in the interrupt
+    /// controller, and must be safe to call from any context.
+    fn pend(&self);
+}
+
+/// An executor that polls its tasks from within an interrupt handler.
+///
+/// The interrupt handler must call `on_interrupt()`, and the interrupt must be pended when
+/// `Pender::pend` is called. Every task started on the executor runs at the priority of
+/// the interrupt.
+///
+/// Like every executor it expires timers, taking the timer queue lock in the interrupt
+/// handler, see `critical_section` for when that is safe.
+pub struct InterruptExecutor {
+    executor: Executor,
+}
+
+impl InterruptExecutor {
+    /// Create a new interrupt executor.
+    ///
+    /// # Arguments
+    ///
+    /// * `pender` - Pends the interrupt running the executor.
+    pub const fn new(pender: &'static dyn Pender) -> Self {
+        Self {
+            executor: Executor::with_wake_hooks(&super::idle::Spin, Some(pender)),
+        }
+    }
+
+    /// Start a task, scheduling it to be run by this executor.
+    ///
+    /// # Arguments
+    ///
+    /// * `task` - The task to start.
+    pub fn start<'a, T: Task + TypedTask + 'static>(&'static self, task: Pin<&'a mut T>) -> TaskResult<'a, T::Output> {
+        self.executor.start(task)
+    }
+
+    /// Poll all ready tasks, this must be called from the interrupt handler.
+    ///
+    /// This expires timers as well, see `InterruptExecutor`.
+    ///
+    /// # Panics
+    ///
+    /// Any nested calls will cause a panic.
+    pub fn on_interrupt(&'static self) {
+        self.executor.poll_ready_tasks();
+    }
+
+    /// The number of times a task was polled while lower priority tasks were ready.
+    pub fn starved_polls(&self) -> usize {
+        self.executor.starved_polls()
+    }
+}
+
+#[cfg(feature = "std")]
+struct SimulatedController {
+    active: [usize; 256],
+}
+
+#[cfg(feature = "std")]
+impl SimulatedController {
+    fn can_start(&self, priority: u8) -> bool {
+        self.active[priority as usize..].iter().all(|count| *count == 0)
+    }
+}
+
+#[cfg(feature = "std")]
+static CONTROLLER: std::sync::Mutex<SimulatedController> = std::sync::Mutex::new(SimulatedController { active: [0; 256] });
+#[cfg(feature = "std")]
+static CONTROLLER_CHANGED: std::sync::Condvar = std::sync::Condvar::new();
+
+/// A simulated interrupt, running its handler on a dedicated thread.
+///
+/// Pending the interrupt while it's already pending has no effect. A pended handler only
+/// starts when no handler of the same or higher priority is running, higher priority
+/// handlers can start while a lower priority handler runs. Thread mode code, such as
+/// `run()`, runs at a lower priority than all simulated interrupts.
+///
+/// ## Example
+///
+/// ```
+/// use uio::executor::{InterruptExecutor, ThreadInterrupt};
+///
+/// static INTERRUPT: ThreadInterrupt = ThreadInterrupt::new(1);
+/// static EXECUTOR: InterruptExecutor = InterruptExecutor::new(&INTERRUPT);
+///
+/// async fn interrupt_task() -> u32 {
+///     7
+/// }
+///
+/// fn main() {
+///     INTERRUPT.spawn(|| EXECUTOR.on_interrupt());
+///
+///     uio::task_decl!(task, interrupt_task());
+///     let result = EXECUTOR.start(task);
+///     assert_eq!(uio::executor::run_until(result), Ok(7));
+/// }
+/// ```
+///
+/// A higher priority interrupt preempts a running handler, an interrupt of the same
+/// priority waits for it to finish, and pending an interrupt from its own handler runs the
+/// handler again:
+///
+/// ```
+/// use std::sync::Mutex;
+/// use std::time::Duration;
+/// use uio::executor::{Pender, ThreadInterrupt};
+///
+/// static LOW: ThreadInterrupt = ThreadInterrupt::new(1);
+/// static SAME: ThreadInterrupt = ThreadInterrupt::new(1);
+/// static HIGH: ThreadInterrupt = ThreadInterrupt::new(2);
+/// static LOG: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+///
+/// fn log(entry: &'static str) -> usize {
+///     let mut log = LOG.lock().unwrap();
+///     log.push(entry);
+///     log.iter().filter(|logged| **logged == entry).count()
+/// }
+///
+/// fn wait_for(entries: usize) {
+///     while LOG.lock().unwrap().len() < entries {
+///         std::thread::sleep(Duration::from_millis(1));
+///     }
+/// }
+///
+/// fn low() {
+///     log("low start");
+///     SAME.pend();
+///     HIGH.pend();
+///     // Both runs of `HIGH` preempt this handler, `SAME` must not start meanwhile.
+///     wait_for(3);
+///     std::thread::sleep(Duration::from_millis(20));
+///     log("low end");
+/// }
+///
+/// fn same() {
+///     log("same");
+/// }
+///
+/// fn high() {
+///     if log("high") == 1 {
+///         HIGH.pend();
+///     }
+/// }
+///
+/// fn main() {
+///     LOW.spawn(low);
+///     SAME.spawn(same);
+///     HIGH.spawn(high);
+///     LOW.pend();
+///     wait_for(5);
+///     assert_eq!(*LOG.lock().unwrap(), ["low start", "high", "high", "low end", "same"]);
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub struct ThreadInterrupt {
+    priority: u8,
+    pending: std::sync::atomic::AtomicBool,
+}
+
+#[cfg(feature = "std")]
+impl ThreadInterrupt {
+    /// Create a new simulated interrupt with the specified priority, higher values
+    /// preempt lower values.
+    pub const fn new(priority: u8) -> Self {
+        Self {
+            priority,
+            pending: std::sync::atomic::AtomicBool::new(false),
+        }
+    }
+
+    /// Spawn the thread running `handler` every time the interrupt is pended.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - The interrupt handler.
+    pub fn spawn(&'static self, handler: fn()) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || loop {
+            {
+                let mut controller = CONTROLLER.lock().unwrap();
+                while !(self.pending.load(std::sync::atomic::Ordering::Acquire) && controller.can_start(self.priority)) {
+                    controller = CONTROLLER_CHANGED.wait(controller).unwrap();
+                }
+                self.pending.store(false, std::sync::atomic::Ordering::Release);
+                controller.active[self.priority as usize] += 1;
+            }
+            handler();
+            CONTROLLER.lock().unwrap().active[self.priority as usize] -= 1;
+            CONTROLLER_CHANGED.notify_all();
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl Pender for ThreadInterrupt {
+    fn pend(&self) {
+        let _controller = CONTROLLER.lock().unwrap();
+        self.pending.store(true, std::sync::atomic::Ordering::Release);
+        CONTROLLER_CHANGED.notify_all();
+    }
+}
@@ -5,7 +5,11 @@ use core::task::{Context, Poll, Waker};
 
 use core::sync::atomic::{AtomicU8, Ordering};
 
+use crate::critical_section::update;
+
+/// Trait for types wrapping a future that can be cancelled.
 pub trait Cancelable {
+    /// Cancel the wrapped future, dropping it in place.
     fn cancel_future(&mut self);
 }
 
@@ -29,26 +33,21 @@ impl<T> Value<T> {
 
     pub fn set(&mut self, value: T) {
         self.value = MaybeUninit::new(value);
-        self.flags.fetch_or(HAS_VALUE_FLAG, Ordering::AcqRel);
-        self.take_waker().and_then(|w| {
-            w.wake();
-            Some(())
-        });
+        update(&self.flags, |flags| flags | HAS_VALUE_FLAG);
+        if let Some(waker) = self.take_waker() {
+            waker.wake();
+        }
     }
 
     fn has_value(&self) -> bool {
         (self.flags.load(Ordering::Acquire) & HAS_VALUE_FLAG) != 0
     }
 
-    fn has_waker(&self) -> bool {
-        (self.flags.load(Ordering::Acquire) & HAS_WAKER_FLAG) != 0
-    }
-
     fn take_waker(&mut self) -> Option<Waker> {
-        if !self.has_waker() {
+        // Clearing the flag decides who owns the stored waker, `set` and `poll` may race.
+        if update(&self.flags, |flags| flags & !HAS_WAKER_FLAG) & HAS_WAKER_FLAG == 0 {
             None
         } else {
-            self.flags.fetch_and(!HAS_WAKER_FLAG, Ordering::AcqRel);
             Some(unsafe {
                 core::mem::replace(&mut self.waker, MaybeUninit::uninit()).assume_init()
             })
@@ -56,9 +55,24 @@ impl<T> Value<T> {
     }
 
     unsafe fn take_value(&mut self) -> T {
-        self.flags.fetch_and(!HAS_VALUE_FLAG, Ordering::AcqRel);
+        update(&self.flags, |flags| flags & !HAS_VALUE_FLAG);
         core::mem::replace(&mut self.value, MaybeUninit::uninit()).assume_init()
     }
+
+    /// Take the value if it has been set.
+    pub(crate) fn take(&mut self) -> Option<T> {
+        if self.has_value() {
+            Some(unsafe { self.take_value() })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Default for Value<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T> Drop for Value<T> {
@@ -79,10 +93,18 @@ impl<T: core::marker::Unpin> Future for Value<T> {
         //let mut_self = unsafe { self.get_unchecked_mut() };
 
         if self.has_value() {
+            return Poll::Ready(unsafe { self.take_value() });
+        }
+
+        self.take_waker();
+        self.waker = MaybeUninit::new(cx.waker().clone());
+        update(&self.flags, |flags| flags | HAS_WAKER_FLAG);
+        // The value might have been set, from another thread or interrupt, before the
+        // waker was stored.
+        if self.has_value() {
+            self.take_waker();
             Poll::Ready(unsafe { self.take_value() })
         } else {
-            self.waker = MaybeUninit::new(cx.waker().clone());
-            self.flags.fetch_or(HAS_WAKER_FLAG, Ordering::AcqRel);
             Poll::Pending
         }
     }
@@ -0,0 +1,427 @@
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::marker::PhantomPinned;
+use core::mem::MaybeUninit;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use core::task::{Context, Poll};
+
+use super::waker::Waker;
+use crate::critical_section::{swap, update};
+use crate::executor::TaskResult;
+use crate::task::{Task, TaskWaker};
+
+/// Alignment of the storage for the future of a running async handler.
+const HANDLER_ALIGN: usize = 16;
+
+/// Queue of deferred interrupt work, the bottom halves of interrupt handlers.
+///
+/// Interrupt handlers schedule `DeferredWork` items that are too slow to handle in the
+/// interrupt handler itself. The items are run, in the order they were scheduled, by the
+/// drain task built into the queue, started with `start()` or taken with `task()`.
+///
+/// Each item carries its own handler. Async handlers run one at a time, each awaited
+/// before the next item is run, and their futures are kept in storage of `N` bytes inside
+/// the drain task. An item whose handler future doesn't fit fails to compile.
+///
+/// Scheduling links the statically allocated item into the queue, it never allocates and
+/// never waits, so it is safe from interrupt handlers.
+///
+/// ## Example
+///
+/// ```
+/// use core::sync::atomic::{AtomicUsize, Ordering};
+/// use uio::executor::yield_now;
+/// use uio::interrupt::{Deferred, DeferredWork};
+///
+/// static DEFERRED: Deferred = Deferred::new();
+/// static RX_DONE: DeferredWork = DeferredWork::new(&DEFERRED, rx_done);
+/// static TX_DONE: DeferredWork = DeferredWork::new_async(&DEFERRED, tx_done);
+/// static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+/// static SENT: AtomicUsize = AtomicUsize::new(0);
+///
+/// fn rx_done(count: usize) {
+///     RECEIVED.fetch_add(count, Ordering::AcqRel);
+/// }
+///
+/// async fn tx_done(count: usize) {
+///     yield_now().await;
+///     SENT.fetch_add(count, Ordering::AcqRel);
+/// }
+///
+/// fn main() {
+///     // A thread plays the part of the interrupt handler, firing at random intervals.
+///     let interrupt = std::thread::spawn(|| {
+///         for _ in 0..100 {
+///             std::thread::sleep(std::time::Duration::from_micros(rand::random::<u64>() % 200));
+///             RX_DONE.schedule();
+///             TX_DONE.schedule();
+///         }
+///     });
+///     uio::executor::block_on(async {
+///         let _drain = DEFERRED.start();
+///         while RECEIVED.load(Ordering::Acquire) < 100 || SENT.load(Ordering::Acquire) < 100 {
+///             yield_now().await;
+///         }
+///     });
+///     interrupt.join().unwrap();
+///     assert_eq!(RECEIVED.load(Ordering::Acquire), 100);
+///     assert!(RX_DONE.coalesced() < 100);
+/// }
+/// ```
+pub struct Deferred<const N: usize = 64> {
+    queue: Queue,
+    taken: AtomicBool,
+    task: UnsafeCell<Option<Task<Drain<N>>>>,
+    task_waker: TaskWaker,
+}
+
+unsafe impl<const N: usize> Sync for Deferred<N> {}
+
+impl<const N: usize> Deferred<N> {
+    pub const fn new() -> Self {
+        Self {
+            queue: Queue {
+                head: AtomicPtr::new(core::ptr::null_mut()),
+                waker: Waker::new(),
+            },
+            taken: AtomicBool::new(false),
+            task: UnsafeCell::new(None),
+            task_waker: TaskWaker::new(),
+        }
+    }
+
+    /// Start the drain task on the default executor, it never finishes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the drain task was already started or taken.
+    pub fn start(&'static self) -> TaskResult<'static, ()> {
+        crate::executor::start(self.task())
+    }
+
+    /// Take the drain task, to start it on an executor of choice, for example an
+    /// `InterruptExecutor` running at a low interrupt priority.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the drain task was already started or taken.
+    #[allow(clippy::mut_from_ref)]
+    pub fn task(&'static self) -> Pin<&'static mut Task<Drain<N>>> {
+        if swap(&self.taken, true) {
+            panic!("The drain task of a Deferred can only be taken once");
+        }
+        unsafe {
+            // Only ever reached once, the task is never moved after this.
+            let task = &mut *self.task.get();
+            *task = Some(Task::new(Drain::new(&self.queue), &self.task_waker));
+            Pin::new_unchecked(task.as_mut().unwrap())
+        }
+    }
+}
+
+impl<const N: usize> Default for Deferred<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The scheduled items of a `Deferred`, independent of its handler storage size.
+struct Queue {
+    head: AtomicPtr<DeferredWork>,
+    waker: Waker,
+}
+
+impl Queue {
+    fn push(&self, work: &'static DeferredWork) {
+        let work_ptr = work as *const DeferredWork as *mut DeferredWork;
+        update(&self.head, |head| {
+            work.next.store(head, Ordering::Relaxed);
+            work_ptr
+        });
+        self.waker.wake();
+    }
+
+    /// Take all scheduled items, in the order they were scheduled, or register to be woken
+    /// when there are none.
+    fn poll_batch(&self, cx: &mut Context<'_>) -> Option<*mut DeferredWork> {
+        // Register before taking, a schedule between the two is then seen either way.
+        self.waker.register(cx.waker());
+        let mut head = swap(&self.head, core::ptr::null_mut());
+        let mut reversed: *mut DeferredWork = core::ptr::null_mut();
+        while let Some(work) = unsafe { head.as_ref() } {
+            head = work.next.load(Ordering::Relaxed);
+            work.next.store(reversed, Ordering::Relaxed);
+            reversed = work as *const DeferredWork as *mut DeferredWork;
+        }
+        if reversed.is_null() {
+            None
+        } else {
+            Some(reversed)
+        }
+    }
+}
+
+#[repr(C, align(16))]
+struct HandlerStorage<const N: usize>(MaybeUninit<[u8; N]>);
+
+/// The future run by the drain task of a `Deferred`, it never finishes.
+pub struct Drain<const N: usize> {
+    queue: &'static Queue,
+    /// Items of the current batch that haven't run yet, in schedule order.
+    batch: *mut DeferredWork,
+    /// The item whose handler future is in `storage`.
+    running: Option<&'static DeferredWork>,
+    storage: HandlerStorage<N>,
+    _pinned: PhantomPinned,
+}
+
+impl<const N: usize> Drain<N> {
+    fn new(queue: &'static Queue) -> Self {
+        Self {
+            queue,
+            batch: core::ptr::null_mut(),
+            running: None,
+            storage: HandlerStorage(MaybeUninit::uninit()),
+            _pinned: PhantomPinned,
+        }
+    }
+}
+
+impl<const N: usize> Future for Drain<N> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // The handler futures in `storage` are never moved.
+        let this = unsafe { self.get_unchecked_mut() };
+        let storage = this.storage.0.as_mut_ptr() as *mut u8;
+        loop {
+            if let Some(work) = this.running {
+                let future = work.future.as_ref().unwrap();
+                if unsafe { (future.poll)(storage, cx) }.is_pending() {
+                    return Poll::Pending;
+                }
+                this.running = None;
+                unsafe { (future.drop)(storage) };
+            }
+            if this.batch.is_null() {
+                match this.queue.poll_batch(cx) {
+                    Some(batch) => this.batch = batch,
+                    None => return Poll::Pending,
+                }
+            }
+            let work = unsafe { &*this.batch };
+            // Read the link first, the item can be scheduled again once it is taken.
+            this.batch = work.next.load(Ordering::Relaxed);
+            let count = work.take();
+            if count == 0 {
+                // Already run along with an earlier schedule.
+                continue;
+            }
+            match &work.future {
+                None => (work.handler)(count),
+                Some(future) => {
+                    unsafe { (future.start)(work.handler, storage, count) };
+                    this.running = Some(work);
+                }
+            }
+        }
+    }
+}
+
+impl<const N: usize> Drop for Drain<N> {
+    fn drop(&mut self) {
+        if let Some(work) = self.running.take() {
+            let storage = self.storage.0.as_mut_ptr() as *mut u8;
+            unsafe { (work.future.as_ref().unwrap().drop)(storage) };
+        }
+    }
+}
+
+/// Type erased operations on the future returned by an async handler.
+struct HandlerFuture {
+    /// Call the handler, storing its future.
+    start: unsafe fn(fn(usize), *mut u8, usize),
+    poll: unsafe fn(*mut u8, &mut Context<'_>) -> Poll<()>,
+    drop: unsafe fn(*mut u8),
+}
+
+unsafe fn start_future<F: Future<Output = ()>>(handler: fn(usize), storage: *mut u8, count: usize) {
+    let handler: fn(usize) -> F = core::mem::transmute(handler);
+    (storage as *mut F).write(handler(count));
+}
+
+unsafe fn poll_future<F: Future<Output = ()>>(storage: *mut u8, cx: &mut Context<'_>) -> Poll<()> {
+    Pin::new_unchecked(&mut *(storage as *mut F)).poll(cx)
+}
+
+unsafe fn drop_future<F>(storage: *mut u8) {
+    core::ptr::drop_in_place(storage as *mut F);
+}
+
+/// A statically allocated work item, scheduled by interrupt handlers and run by the
+/// drain task of its `Deferred` queue.
+///
+/// Scheduling an item that is already pending doesn't queue it again, the schedules are
+/// coalesced into one run. Handlers are called with the number of schedules they handle.
+pub struct DeferredWork {
+    queue: &'static Queue,
+    /// The handler, transmuted from `fn(usize) -> F` for async handlers.
+    handler: fn(usize),
+    future: Option<HandlerFuture>,
+    pending: AtomicBool,
+    count: AtomicUsize,
+    coalesced: AtomicUsize,
+    next: AtomicPtr<DeferredWork>,
+}
+
+impl DeferredWork {
+    /// Create a work item on `deferred`, calling `handler` on the drain task.
+    pub const fn new<const N: usize>(deferred: &'static Deferred<N>, handler: fn(usize)) -> Self {
+        Self::with_handler(&deferred.queue, handler, None)
+    }
+
+    /// Create a work item on `deferred`, awaiting the future returned by `handler` on the
+    /// drain task.
+    ///
+    /// The future must fit the handler storage of `deferred`, of `N` bytes aligned to 16,
+    /// which is checked at compile time.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use uio::executor::yield_now;
+    /// use uio::interrupt::{Deferred, DeferredWork};
+    /// use uio::sync::Signal;
+    ///
+    /// // Room for a handler future holding a 256 byte buffer.
+    /// static DEFERRED: Deferred<512> = Deferred::new();
+    /// static FRAME_DONE: DeferredWork = DeferredWork::new_async(&DEFERRED, frame_done);
+    /// static CHECKSUM: Signal<u32> = Signal::new();
+    ///
+    /// async fn frame_done(_count: usize) {
+    ///     let frame = [1u8; 256];
+    ///     yield_now().await;
+    ///     CHECKSUM.signal(frame.iter().map(|byte| *byte as u32).sum());
+    /// }
+    ///
+    /// fn main() {
+    ///     FRAME_DONE.schedule();
+    ///     uio::executor::block_on(async {
+    ///         let _drain = DEFERRED.start();
+    ///         assert_eq!(CHECKSUM.wait().await, 256);
+    ///     });
+    /// }
+    /// ```
+    ///
+    /// The default storage is too small for it:
+    ///
+    /// ```compile_fail,E0080
+    /// # use uio::executor::yield_now;
+    /// # use uio::interrupt::{Deferred, DeferredWork};
+    /// static DEFERRED: Deferred = Deferred::new();
+    /// static FRAME_DONE: DeferredWork = DeferredWork::new_async(&DEFERRED, frame_done);
+    ///
+    /// async fn frame_done(_count: usize) {
+    ///     let frame = [1u8; 256];
+    ///     yield_now().await;
+    ///     drop(frame);
+    /// }
+    /// # fn main() {}
+    /// ```
+    pub const fn new_async<const N: usize, F: Future<Output = ()> + 'static>(
+        deferred: &'static Deferred<N>,
+        handler: fn(usize) -> F,
+    ) -> Self {
+        const {
+            assert!(
+                core::mem::size_of::<F>() <= N && core::mem::align_of::<F>() <= HANDLER_ALIGN,
+                "The handler future doesn't fit the handler storage of the Deferred"
+            )
+        };
+        let future = HandlerFuture {
+            start: start_future::<F>,
+            poll: poll_future::<F>,
+            drop: drop_future::<F>,
+        };
+        // Only ever called after being transmuted back, by `start_future::<F>`.
+        let handler = unsafe { core::mem::transmute::<fn(usize) -> F, fn(usize)>(handler) };
+        Self::with_handler(&deferred.queue, handler, Some(future))
+    }
+
+    const fn with_handler(queue: &'static Queue, handler: fn(usize), future: Option<HandlerFuture>) -> Self {
+        Self {
+            queue,
+            handler,
+            future,
+            pending: AtomicBool::new(false),
+            count: AtomicUsize::new(0),
+            coalesced: AtomicUsize::new(0),
+            next: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    /// Schedule the item to run, this is called by the interrupt handler.
+    ///
+    /// Returns `true` if the item was queued by this call, and `false` if it was already
+    /// pending, in which case this schedule is coalesced into the pending run.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::sync::atomic::{AtomicUsize, Ordering};
+    /// use uio::executor::yield_now;
+    /// use uio::interrupt::{Deferred, DeferredWork};
+    ///
+    /// static DEFERRED: Deferred = Deferred::new();
+    /// static WORK: DeferredWork = DeferredWork::new(&DEFERRED, handle);
+    /// static HANDLED: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// fn handle(count: usize) {
+    ///     assert_eq!(count, 3);
+    ///     HANDLED.fetch_add(1, Ordering::AcqRel);
+    /// }
+    ///
+    /// fn main() {
+    ///     assert!(WORK.schedule());
+    ///     assert!(!WORK.schedule());
+    ///     assert!(!WORK.schedule());
+    ///     assert_eq!(WORK.coalesced(), 2);
+    ///
+    ///     uio::executor::block_on(async {
+    ///         let _drain = DEFERRED.start();
+    ///         while HANDLED.load(Ordering::Acquire) == 0 {
+    ///             yield_now().await;
+    ///         }
+    ///     });
+    ///     assert!(!WORK.is_pending());
+    /// }
+    /// ```
+    pub fn schedule(&'static self) -> bool {
+        update(&self.count, |count| count + 1);
+        if swap(&self.pending, true) {
+            update(&self.coalesced, |coalesced| coalesced + 1);
+            false
+        } else {
+            self.queue.push(self);
+            true
+        }
+    }
+
+    /// Returns `true` if the item is scheduled and hasn't started running yet.
+    pub fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire)
+    }
+
+    /// The total number of schedules coalesced into an already pending run.
+    pub fn coalesced(&self) -> usize {
+        self.coalesced.load(Ordering::Acquire)
+    }
+
+    /// Clear the pending flag and take the number of schedules to handle.
+    fn take(&self) -> usize {
+        // Clear the flag first, a schedule after this queues the item again.
+        swap(&self.pending, false);
+        swap(&self.count, 0)
+    }
+}
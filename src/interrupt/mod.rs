@@ -1 +1,17 @@
+//! Interrupt helpers
+//!
+//! Interrupt handlers wake tasks through the wakers in `waker`. Tasks wait for an
+//! interrupt with `wait()`, or with an `InterruptFlag` when the interrupt may fire before
+//! the task starts waiting. A `Registry` maps interrupt numbers to handlers and wakers.
+//! Work too slow for an interrupt handler is handed off to a task through `Deferred`.
+
+mod deferred;
+mod registry;
+mod wait;
 pub mod waker;
+
+pub use deferred::{Deferred, DeferredWork, Drain};
+pub use registry::Registry;
+#[cfg(feature = "std")]
+pub use registry::SimulatedInterrupts;
+pub use wait::{wait, FlagFuture, InterruptFlag, WaitFuture};
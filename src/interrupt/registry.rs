@@ -0,0 +1,224 @@
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use super::waker::{Waker, WakerRef};
+
+/// Maps interrupt numbers to handlers and wakers.
+///
+/// Drivers register interest in an interrupt by number, and the interrupt vectors, or a
+/// simulation such as `SimulatedInterrupts`, call `dispatch()` with the number of the
+/// interrupt that fired. Dispatching runs the registered handler, if any, and then wakes
+/// the registered waker.
+///
+/// # Panics
+///
+/// All methods panic if the interrupt number is `N` or larger.
+pub struct Registry<const N: usize> {
+    handlers: [AtomicPtr<()>; N],
+    wakers: [WakerRef; N],
+}
+
+impl<const N: usize> Registry<N> {
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const NO_HANDLER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+        #[allow(clippy::declare_interior_mutable_const)]
+        const NO_WAKER: WakerRef = WakerRef::new_empty();
+        Self {
+            handlers: [NO_HANDLER; N],
+            wakers: [NO_WAKER; N],
+        }
+    }
+
+    /// Run `handler` every time interrupt `irq` is dispatched, replacing any earlier handler.
+    pub fn register_handler(&self, irq: usize, handler: fn()) {
+        self.handlers[irq].store(handler as *mut (), Ordering::Release);
+    }
+
+    pub fn unregister_handler(&self, irq: usize) {
+        self.handlers[irq].store(core::ptr::null_mut(), Ordering::Release);
+    }
+
+    /// Wake `waker` every time interrupt `irq` is dispatched, replacing any earlier waker.
+    pub fn register_waker(&self, irq: usize, waker: &'static Waker) {
+        self.wakers[irq].assign(&WakerRef::new(waker));
+    }
+
+    pub fn unregister_waker(&self, irq: usize) {
+        self.wakers[irq].assign(&WakerRef::new_empty());
+    }
+
+    /// Run the handler and wake the waker registered for interrupt `irq`.
+    pub fn dispatch(&self, irq: usize) {
+        let handler = self.handlers[irq].load(Ordering::Acquire);
+        if !handler.is_null() {
+            // Only ever stored from a `fn()` by `register_handler`.
+            let handler = unsafe { core::mem::transmute::<*mut (), fn()>(handler) };
+            handler();
+        }
+        self.wakers[irq].try_wake();
+    }
+}
+
+impl<const N: usize> Default for Registry<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+struct SimulatedState<const N: usize> {
+    pending: [bool; N],
+    masked: [bool; N],
+    priorities: [u8; N],
+}
+
+#[cfg(feature = "std")]
+impl<const N: usize> SimulatedState<N> {
+    /// Clear and return the highest priority pending, unmasked, interrupt. Ties are broken
+    /// by the lowest interrupt number.
+    fn take_next(&mut self) -> Option<usize> {
+        let mut next: Option<usize> = None;
+        for irq in 0..N {
+            if self.pending[irq] && !self.masked[irq] && next.is_none_or(|next| self.priorities[irq] > self.priorities[next]) {
+                next = Some(irq);
+            }
+        }
+        if let Some(irq) = next {
+            self.pending[irq] = false;
+        }
+        next
+    }
+}
+
+/// Simulated interrupt controller, dispatching pended interrupts to a `Registry`.
+///
+/// Pending an interrupt that is already pending has no effect. Pended interrupts are
+/// dispatched one at a time, highest priority first, either on the calling thread by
+/// `dispatch_pending()` or on a dedicated thread started with `spawn()`. Masked interrupts
+/// stay pending until they're unmasked. All interrupts start unmasked with priority 0.
+///
+/// ## Example
+///
+/// ```
+/// use std::sync::Mutex;
+/// use uio::interrupt::waker::Waker;
+/// use uio::interrupt::{Registry, SimulatedInterrupts};
+///
+/// const UART: usize = 3;
+/// const TIMER: usize = 5;
+///
+/// static REGISTRY: Registry<8> = Registry::new();
+/// static INTERRUPTS: SimulatedInterrupts<8> = SimulatedInterrupts::new(&REGISTRY);
+/// static UART_WAKER: Waker = Waker::new();
+/// static HANDLED: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+///
+/// fn main() {
+///     REGISTRY.register_handler(UART, || HANDLED.lock().unwrap().push(UART));
+///     REGISTRY.register_handler(TIMER, || HANDLED.lock().unwrap().push(TIMER));
+///     INTERRUPTS.set_priority(TIMER, 2);
+///     INTERRUPTS.set_priority(UART, 1);
+///
+///     INTERRUPTS.mask(UART);
+///     INTERRUPTS.pend(UART);
+///     INTERRUPTS.pend(TIMER);
+///     assert_eq!(INTERRUPTS.dispatch_pending(), 1);
+///     assert!(INTERRUPTS.is_pending(UART));
+///
+///     INTERRUPTS.unmask(UART);
+///     INTERRUPTS.pend(TIMER);
+///     assert_eq!(INTERRUPTS.dispatch_pending(), 2);
+///     assert_eq!(*HANDLED.lock().unwrap(), [TIMER, TIMER, UART]);
+///
+///     // End to end, a task waits for the UART interrupt pended by another thread.
+///     REGISTRY.register_waker(UART, &UART_WAKER);
+///     INTERRUPTS.spawn();
+///     std::thread::spawn(|| {
+///         while !UART_WAKER.has_waker() {
+///             std::thread::yield_now();
+///         }
+///         INTERRUPTS.pend(UART);
+///     });
+///     uio::executor::block_on(uio::interrupt::wait(&UART_WAKER));
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub struct SimulatedInterrupts<const N: usize> {
+    registry: &'static Registry<N>,
+    state: std::sync::Mutex<SimulatedState<N>>,
+    changed: std::sync::Condvar,
+}
+
+#[cfg(feature = "std")]
+impl<const N: usize> SimulatedInterrupts<N> {
+    /// Create a new controller, dispatching interrupts to `registry`.
+    pub const fn new(registry: &'static Registry<N>) -> Self {
+        Self {
+            registry,
+            state: std::sync::Mutex::new(SimulatedState {
+                pending: [false; N],
+                masked: [false; N],
+                priorities: [0; N],
+            }),
+            changed: std::sync::Condvar::new(),
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut SimulatedState<N>)) {
+        f(&mut self.state.lock().unwrap());
+        self.changed.notify_all();
+    }
+
+    /// Set interrupt `irq` pending, this can be called from any thread.
+    pub fn pend(&self, irq: usize) {
+        self.update(|state| state.pending[irq] = true);
+    }
+
+    pub fn is_pending(&self, irq: usize) -> bool {
+        self.state.lock().unwrap().pending[irq]
+    }
+
+    pub fn mask(&self, irq: usize) {
+        self.update(|state| state.masked[irq] = true);
+    }
+
+    pub fn unmask(&self, irq: usize) {
+        self.update(|state| state.masked[irq] = false);
+    }
+
+    /// Set the priority of interrupt `irq`, higher values are dispatched first.
+    pub fn set_priority(&self, irq: usize, priority: u8) {
+        self.update(|state| state.priorities[irq] = priority);
+    }
+
+    fn take_next(&self) -> Option<usize> {
+        self.state.lock().unwrap().take_next()
+    }
+
+    /// Dispatch pending, unmasked, interrupts on the calling thread until none is left.
+    ///
+    /// Returns the number of dispatched interrupts.
+    pub fn dispatch_pending(&self) -> usize {
+        let mut dispatched = 0;
+        while let Some(irq) = self.take_next() {
+            self.registry.dispatch(irq);
+            dispatched += 1;
+        }
+        dispatched
+    }
+
+    /// Spawn a thread dispatching interrupts as they're pended.
+    pub fn spawn(&'static self) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || loop {
+            let irq = {
+                let mut state = self.state.lock().unwrap();
+                loop {
+                    if let Some(irq) = state.take_next() {
+                        break irq;
+                    }
+                    state = self.changed.wait(state).unwrap();
+                }
+            };
+            self.registry.dispatch(irq);
+        })
+    }
+}
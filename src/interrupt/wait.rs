@@ -0,0 +1,161 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
+
+use super::waker::Waker;
+use crate::critical_section::{store, swap};
+
+/// Wait until `waker` is woken by an interrupt.
+///
+/// The future registers with `waker` when first polled, and completes once the registered
+/// waker has been taken by `Waker::wake` or `Waker::try_wake`. An interrupt firing before
+/// the first poll is not seen, use an `InterruptFlag` if that can happen.
+///
+/// ## Example
+///
+/// ```
+/// use uio::interrupt::waker::Waker;
+///
+/// static DMA_DONE: Waker = Waker::new();
+///
+/// fn main() {
+///     // A thread plays the part of the interrupt handler, firing after a random delay.
+///     let interrupt = std::thread::spawn(|| {
+///         while !DMA_DONE.has_waker() {
+///             std::thread::yield_now();
+///         }
+///         std::thread::sleep(std::time::Duration::from_micros(rand::random::<u64>() % 1_000));
+///         DMA_DONE.wake();
+///     });
+///     uio::executor::block_on(uio::interrupt::wait(&DMA_DONE));
+///     interrupt.join().unwrap();
+/// }
+/// ```
+pub fn wait(waker: &Waker) -> WaitFuture<'_> {
+    WaitFuture {
+        waker,
+        registered: false,
+    }
+}
+
+/// Future returned by `wait()`.
+pub struct WaitFuture<'a> {
+    waker: &'a Waker,
+    registered: bool,
+}
+
+impl<'a> Future for WaitFuture<'a> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.registered && !self.waker.has_waker() {
+            return Poll::Ready(());
+        }
+        self.waker.register(cx.waker());
+        self.registered = true;
+        Poll::Pending
+    }
+}
+
+/// An interrupt pending bit combined with a waker.
+///
+/// The interrupt handler calls `signal()`, setting the pending bit and waking the waiting
+/// task. `wait()` completes when the bit is set, clearing it, so an interrupt firing before
+/// the task starts waiting is not lost. Several interrupts before the task runs are
+/// coalesced into one.
+///
+/// ## Example
+///
+/// ```
+/// use core::sync::atomic::{AtomicUsize, Ordering};
+/// use uio::interrupt::InterruptFlag;
+///
+/// static RX: InterruptFlag = InterruptFlag::new();
+/// static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+///
+/// async fn receiver() -> usize {
+///     let mut wakeups = 0;
+///     while RECEIVED.load(Ordering::Acquire) < 100 {
+///         RX.wait().await;
+///         wakeups += 1;
+///     }
+///     wakeups
+/// }
+///
+/// fn main() {
+///     // Not lost, even though no task is waiting yet.
+///     RX.signal();
+///     uio::executor::block_on(RX.wait());
+///
+///     // A thread plays the part of the interrupt handler, firing at random intervals.
+///     let interrupt = std::thread::spawn(|| {
+///         for _ in 0..100 {
+///             std::thread::sleep(std::time::Duration::from_micros(rand::random::<u64>() % 200));
+///             RECEIVED.fetch_add(1, Ordering::AcqRel);
+///             RX.signal();
+///         }
+///     });
+///     let wakeups = uio::executor::block_on(receiver());
+///     assert!((1..=100).contains(&wakeups));
+///     interrupt.join().unwrap();
+/// }
+/// ```
+pub struct InterruptFlag {
+    pending: AtomicBool,
+    waker: Waker,
+}
+
+impl InterruptFlag {
+    pub const fn new() -> Self {
+        Self {
+            pending: AtomicBool::new(false),
+            waker: Waker::new(),
+        }
+    }
+
+    /// Set the pending bit and wake the waiting task, this is called by the interrupt handler.
+    pub fn signal(&self) {
+        store(&self.pending, true);
+        self.waker.wake();
+    }
+
+    /// Wait until the pending bit is set, and clear it.
+    pub fn wait(&self) -> FlagFuture<'_> {
+        FlagFuture { flag: self }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire)
+    }
+
+    /// Clear the pending bit, returning `true` if it was set.
+    pub fn clear(&self) -> bool {
+        swap(&self.pending, false)
+    }
+}
+
+impl Default for InterruptFlag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future returned by `InterruptFlag::wait`.
+pub struct FlagFuture<'a> {
+    flag: &'a InterruptFlag,
+}
+
+impl<'a> Future for FlagFuture<'a> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Register before checking, a signal between the two is then seen either way.
+        self.flag.waker.register(cx.waker());
+        if self.flag.clear() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
@@ -1,55 +1,205 @@
-use core::cell::Cell;
-use core::mem::MaybeUninit;
-use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, Ordering};
 use core::task;
 
+use crate::critical_section::{store, swap, update};
+use crate::lock::Lock;
+
+const WAITING: u8 = 0b0000_0000;
+const REGISTERING: u8 = 0b0000_0001;
+const WAKING: u8 = 0b0000_0010;
+
+/// A task waker slot, registered by a task and woken from an interrupt handler.
+///
+/// Registering and waking follow the `AtomicWaker` protocol: both sides claim the slot
+/// through a state word before touching it, so they can race freely.
+///
+/// * A register that races with a wake completes, and then wakes the new waker itself.
+/// * A wake that races with another wake does nothing, the other wake takes the waker.
+/// * Registering a waker that `will_wake` the stored waker skips the clone, otherwise the
+///   replaced waker is dropped.
+///
+/// Registering from several contexts concurrently is not supported, one of the wakers is
+/// then simply not registered.
+///
+/// ## Example
+///
+/// ```
+/// use core::future::Future;
+/// use core::pin::Pin;
+/// use core::sync::atomic::{AtomicUsize, Ordering};
+/// use core::task::{Context, Poll};
+/// use uio::interrupt::waker::Waker;
+///
+/// static WAKER: Waker = Waker::new();
+/// static EVENTS: AtomicUsize = AtomicUsize::new(0);
+///
+/// struct WaitForEvents(usize);
+///
+/// impl Future for WaitForEvents {
+///     type Output = ();
+///     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+///         WAKER.register(cx.waker());
+///         if EVENTS.load(Ordering::Acquire) >= self.0 {
+///             Poll::Ready(())
+///         } else {
+///             Poll::Pending
+///         }
+///     }
+/// }
+///
+/// fn main() {
+///     // A thread plays the part of the interrupt handler, racing with the registering task.
+///     let interrupt = std::thread::spawn(|| {
+///         for _ in 0..20_000 {
+///             EVENTS.fetch_add(1, Ordering::AcqRel);
+///             WAKER.wake();
+///         }
+///     });
+///     // A lost wake up would leave the executor waiting forever.
+///     for events in (1_000..=20_000).step_by(1_000) {
+///         uio::executor::block_on(WaitForEvents(events));
+///     }
+///     interrupt.join().unwrap();
+/// }
+/// ```
+///
+/// Replaced wakers are dropped:
+///
+/// ```
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use std::sync::Arc;
+/// use std::task::Wake;
+/// use uio::interrupt::waker::Waker;
+///
+/// struct Flag(AtomicBool);
+///
+/// impl Wake for Flag {
+///     fn wake(self: Arc<Self>) {
+///         self.0.store(true, Ordering::Release);
+///     }
+/// }
+///
+/// fn main() {
+///     let waker = Waker::new();
+///     let first = Arc::new(Flag(AtomicBool::new(false)));
+///     let second = Arc::new(Flag(AtomicBool::new(false)));
+///
+///     waker.register(&first.clone().into());
+///     assert_eq!(Arc::strong_count(&first), 2);
+///     waker.register(&second.clone().into());
+///     assert_eq!(Arc::strong_count(&first), 1);
+///
+///     assert!(waker.try_wake());
+///     assert!(second.0.load(Ordering::Acquire));
+///     assert!(!first.0.load(Ordering::Acquire));
+///     assert_eq!(Arc::strong_count(&second), 1);
+///     assert!(!waker.try_wake());
+/// }
+/// ```
 pub struct Waker {
-    waker: core::cell::Cell<MaybeUninit<task::Waker>>,
+    state: AtomicU8,
+    waker: UnsafeCell<Option<task::Waker>>,
     has_waker: AtomicBool,
 }
 
+unsafe impl Send for Waker {}
+unsafe impl Sync for Waker {}
+
 impl Waker {
     pub const fn new() -> Self {
         Self {
-            waker: Cell::new(MaybeUninit::uninit()),
+            state: AtomicU8::new(WAITING),
+            waker: UnsafeCell::new(None),
             has_waker: AtomicBool::new(false),
         }
     }
-    unsafe fn take_waker_impl(&self) -> task::Waker {
-        self.waker.replace(MaybeUninit::uninit()).assume_init()
+
+    /// Register `waker` to be woken by the next call to `wake()`.
+    pub fn register(&self, waker: &task::Waker) {
+        match update(&self.state, |state| if state == WAITING { REGISTERING } else { state }) {
+            WAITING => unsafe {
+                // The slot is claimed, wakes only set the waking bit until it is released.
+                let slot = &mut *self.waker.get();
+                let replaced = match slot {
+                    Some(current) if current.will_wake(waker) => None,
+                    _ => slot.replace(waker.clone()),
+                };
+                self.has_waker.store(true, Ordering::Release);
+
+                if update(&self.state, |state| if state == REGISTERING { WAITING } else { state }) != REGISTERING {
+                    // A wake raced with the registration, wake on its behalf.
+                    let woken = slot.take();
+                    self.has_waker.store(false, Ordering::Release);
+                    swap(&self.state, WAITING);
+                    if let Some(woken) = woken {
+                        woken.wake();
+                    }
+                }
+                drop(replaced);
+            },
+            WAKING => {
+                // A wake is taking the previous waker, the new waker must be woken as well.
+                waker.wake_by_ref();
+            }
+            _ => {
+                // Concurrent registration, not supported.
+            }
+        }
     }
+
+    /// Register `waker` to be woken by the next call to `wake()`.
+    ///
+    /// Equivalent to `register()`, taking the waker by value.
     pub fn set_waker(&self, waker: task::Waker) {
-        self.has_waker.store(false, Ordering::Release);
-        self.waker.replace(MaybeUninit::new(waker));
-        self.has_waker.store(true, Ordering::Release);
+        self.register(&waker);
     }
 
+    /// Take the registered waker, if any.
     pub fn take_waker(&self) -> Option<task::Waker> {
-        if self
-            .has_waker
-            .compare_and_swap(true, false, Ordering::SeqCst)
-        {
-            unsafe { Some(self.take_waker_impl()) }
-        } else {
-            None
+        match update(&self.state, |state| state | WAKING) {
+            WAITING => {
+                let waker = unsafe { (*self.waker.get()).take() };
+                self.has_waker.store(false, Ordering::Release);
+                update(&self.state, |state| state & !WAKING);
+                waker
+            }
+            // Either a registration is in progress and will wake its waker, or another wake
+            // is taking the waker.
+            _ => None,
         }
     }
 
+    /// Wake the registered waker, if any.
+    pub fn wake(&self) {
+        self.try_wake();
+    }
+
+    /// Wake the registered waker, returning `true` if a waker was taken and woken by
+    /// this call.
     pub fn try_wake(&self) -> bool {
-        self.take_waker()
-            .and_then(|w| {
-                w.wake();
-                Some(true)
-            })
-            .unwrap_or(false)
+        match self.take_waker() {
+            Some(waker) => {
+                waker.wake();
+                true
+            }
+            None => false,
+        }
     }
 
+    /// Returns `true` if a waker is registered.
+    ///
+    /// This is only a snapshot, a concurrent register or wake may change it at any time.
     pub fn has_waker(&self) -> bool {
         self.has_waker.load(Ordering::Acquire)
     }
 }
 
-unsafe impl Sync for Waker {}
+impl Default for Waker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 pub struct WakerRef {
     waker: AtomicPtr<Waker>,
@@ -79,17 +229,19 @@ impl WakerRef {
     }
 
     pub fn try_wake(&self) -> bool {
+        unsafe { self.waker_ref().is_some_and(|w| w.try_wake()) }
+    }
+
+    pub fn register(&self, waker: &task::Waker) {
         unsafe {
-            self.waker_ref()
-                .and_then(|w| Some(w.try_wake()))
-                .or(Some(false)).expect("")
+            if let Some(w) = self.waker_ref() {
+                w.register(waker);
+            }
         }
     }
 
     pub fn set_waker(&self, waker: task::Waker) {
-        unsafe {
-            self.waker_ref().and_then(|w| Some(w.set_waker(waker)));
-        }
+        self.register(&waker);
     }
 
     pub fn take_waker(&self) -> Option<task::Waker> {
@@ -106,3 +258,182 @@ impl Clone for WakerRef {
 }
 
 unsafe impl Send for WakerRef {}
+
+/// Error returned by `WakerSet::register` when all slots are in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WakerSetFull;
+
+/// A set of up to `N` task wakers, letting several tasks wait on one interrupt source.
+///
+/// Registering a waker that `will_wake` an already registered waker has no effect. When
+/// all slots are in use `register` fails and the overflow is recorded, see
+/// `take_overflow()`. A task that fails to register should not wait for the interrupt,
+/// it can wake itself to retry later instead.
+///
+/// All methods are safe from interrupt handlers, see `critical_section`.
+///
+/// ## Example
+///
+/// ```
+/// use core::future::Future;
+/// use core::pin::Pin;
+/// use core::sync::atomic::{AtomicU8, Ordering};
+/// use core::task::{Context, Poll};
+/// use uio::interrupt::waker::WakerSet;
+///
+/// // One interrupt shared by all pins of a GPIO port.
+/// static PORT: WakerSet<8> = WakerSet::new();
+/// static EDGES: AtomicU8 = AtomicU8::new(0);
+///
+/// struct WaitForEdge(u8);
+///
+/// impl Future for WaitForEdge {
+///     type Output = ();
+///     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+///         PORT.register(cx.waker()).unwrap();
+///         if EDGES.load(Ordering::Acquire) & (1 << self.0) != 0 {
+///             Poll::Ready(())
+///         } else {
+///             Poll::Pending
+///         }
+///     }
+/// }
+///
+/// fn main() {
+///     uio::task_start!(pin0, WaitForEdge(0));
+///     uio::task_start!(pin3, WaitForEdge(3));
+///     uio::task_start!(pin7, WaitForEdge(7));
+///
+///     // A thread plays the part of the interrupt handler.
+///     std::thread::spawn(|| {
+///         for pin in [3, 0, 7] {
+///             std::thread::sleep(std::time::Duration::from_millis(5));
+///             EDGES.fetch_or(1 << pin, Ordering::AcqRel);
+///             PORT.wake_all();
+///         }
+///     });
+///     uio::executor::run();
+///     assert!(!PORT.take_overflow());
+/// }
+/// ```
+///
+/// Overflow is reported:
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::task::Wake;
+/// use uio::interrupt::waker::{WakerSet, WakerSetFull};
+///
+/// struct Noop;
+///
+/// impl Wake for Noop {
+///     fn wake(self: Arc<Self>) {}
+/// }
+///
+/// fn main() {
+///     let set = WakerSet::<1>::new();
+///     let first = Arc::new(Noop).into();
+///     assert_eq!(set.register(&first), Ok(()));
+///     assert_eq!(set.register(&first), Ok(()));
+///     assert_eq!(set.register(&Arc::new(Noop).into()), Err(WakerSetFull));
+///     assert!(set.take_overflow());
+///     assert!(!set.take_overflow());
+///     assert!(set.wake_one());
+///     assert!(set.is_empty());
+/// }
+/// ```
+pub struct WakerSet<const N: usize> {
+    slots: Lock<[Option<task::Waker>; N]>,
+    overflowed: AtomicBool,
+}
+
+impl<const N: usize> WakerSet<N> {
+    pub const fn new() -> Self {
+        const EMPTY: Option<task::Waker> = None;
+        Self {
+            slots: Lock::new([EMPTY; N]),
+            overflowed: AtomicBool::new(false),
+        }
+    }
+
+    /// Register `waker` to be woken by `wake_all()` or `wake_one()`.
+    pub fn register(&self, waker: &task::Waker) -> Result<(), WakerSetFull> {
+        let registered = self.slots.lock(|slots| {
+            if slots.iter().flatten().any(|registered| registered.will_wake(waker)) {
+                return true;
+            }
+            match slots.iter_mut().find(|slot| slot.is_none()) {
+                Some(slot) => {
+                    *slot = Some(waker.clone());
+                    true
+                }
+                None => false,
+            }
+        });
+        if registered {
+            Ok(())
+        } else {
+            store(&self.overflowed, true);
+            Err(WakerSetFull)
+        }
+    }
+
+    /// Remove a registered waker that `will_wake` the same task as `waker`.
+    ///
+    /// Returns `true` if a waker was removed.
+    pub fn remove(&self, waker: &task::Waker) -> bool {
+        let removed = self.slots.lock(|slots| {
+            slots
+                .iter_mut()
+                .find(|slot| slot.as_ref().is_some_and(|registered| registered.will_wake(waker)))
+                .and_then(Option::take)
+        });
+        removed.is_some()
+    }
+
+    /// Wake, and remove, all registered wakers.
+    pub fn wake_all(&self) {
+        for index in 0..N {
+            if let Some(waker) = self.slots.lock(|slots| slots[index].take()) {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Wake, and remove, one registered waker.
+    ///
+    /// Returns `true` if a waker was woken.
+    pub fn wake_one(&self) -> bool {
+        match self.slots.lock(|slots| slots.iter_mut().find_map(Option::take)) {
+            Some(waker) => {
+                waker.wake();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if a registration has failed since the last call, clearing the flag.
+    pub fn take_overflow(&self) -> bool {
+        swap(&self.overflowed, false)
+    }
+
+    /// The number of registered wakers.
+    pub fn len(&self) -> usize {
+        self.slots.lock(|slots| slots.iter().flatten().count())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> Default for WakerSet<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
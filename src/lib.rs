@@ -61,7 +61,7 @@
 //! async fn task_1() {
 //!     uio::task_start!(task2, task_2(2));
 //!
-//!     println!("Task 2 took {} milliseconds", task2.join().await);
+//!     println!("Task 2 took {} milliseconds", task2.join().await.unwrap());
 //! }
 //!
 //! fn main() {
@@ -72,14 +72,26 @@
 //! }
 //! ```
 
+#[cfg(feature = "std")]
+extern crate std;
+
 pub use pin_utils;
 
+/// Critical sections used to update state shared with interrupt handlers.
+pub mod critical_section;
 /// Executor types, traits and functions.
 pub mod executor;
 /// Asynchronous values.
 pub mod future;
 /// Interrupt traits and helpers.
 pub mod interrupt;
+/// Channels and other primitives for synchronizing tasks.
+pub mod sync;
 /// Types for working with tasks.
 pub mod task;
+/// Clocks, instants and timers.
+pub mod time;
+
+mod lock;
+mod ready_queue;
 
@@ -0,0 +1,33 @@
+//! Crate internal lock protecting intrusive lists shared between tasks and executors.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::AtomicBool;
+
+use crate::critical_section::{CriticalSection, Selected};
+
+/// A lock held in a critical section, only ever held for short, bounded sections.
+///
+/// Locks are taken from interrupt handlers too, by interrupt executors and the interrupt
+/// safe primitives. A handler must never take a lock held by the code it preempted, which
+/// the critical section guarantees only when it masks interrupts, see the
+/// `critical_section` module docs.
+pub(crate) struct Lock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Lock<T> {}
+
+impl<T> Lock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Run `f` with exclusive access to the protected value.
+    pub fn lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        Selected::with(&self.locked, || f(unsafe { &mut *self.value.get() }))
+    }
+}
@@ -0,0 +1,80 @@
+//! Intrusive queue of tasks that are ready to be polled.
+//!
+//! The queue links `TaskWaker`s together through their `next_ready` pointer, so pushing
+//! never allocates and is safe from other threads and interrupts. Only the executor pops
+//! from the queue, and it always takes every queued waker at once.
+
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::critical_section::{swap, update};
+use crate::task::TaskWaker;
+
+pub(crate) struct ReadyQueue {
+    head: AtomicPtr<TaskWaker>,
+}
+
+impl ReadyQueue {
+    pub const fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    /// Push a waker to the queue.
+    ///
+    /// The caller must make sure that the waker isn't already queued, this is done by
+    /// only pushing wakers when their ready flag goes from cleared to set.
+    pub fn push(&self, waker: &'static TaskWaker) {
+        let waker_ptr = waker as *const TaskWaker as *mut TaskWaker;
+        update(&self.head, |head| {
+            waker.next_ready.store(head, Ordering::Relaxed);
+            waker_ptr
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+
+    /// Take all queued wakers, in the order they were pushed.
+    pub fn take_all(&self) -> ReadyBatch {
+        let mut head = swap(&self.head, core::ptr::null_mut());
+        let mut reversed: *mut TaskWaker = core::ptr::null_mut();
+        while let Some(waker) = unsafe { head.as_ref() } {
+            head = waker.next_ready.load(Ordering::Relaxed);
+            waker.next_ready.store(reversed, Ordering::Relaxed);
+            reversed = waker as *const TaskWaker as *mut TaskWaker;
+        }
+        ReadyBatch { next: reversed }
+    }
+}
+
+/// Wakers taken from a `ReadyQueue`.
+///
+/// The link to the next waker is read before a waker is handed out, so a waker can be
+/// pushed to the queue again as soon as its ready flag has been cleared.
+pub(crate) struct ReadyBatch {
+    next: *mut TaskWaker,
+}
+
+impl ReadyBatch {
+    pub const fn empty() -> Self {
+        Self {
+            next: core::ptr::null_mut(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.next.is_null()
+    }
+}
+
+impl Iterator for ReadyBatch {
+    type Item = &'static TaskWaker;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let waker = unsafe { self.next.as_ref()? };
+        self.next = waker.next_ready.load(Ordering::Relaxed);
+        Some(waker)
+    }
+}
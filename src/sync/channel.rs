@@ -0,0 +1,385 @@
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::marker::PhantomData;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use super::wait_queue::{WaitQueue, Waiter};
+use crate::lock::Lock;
+
+/// Error returned by `Sender::send` when all receivers have been dropped, containing the
+/// value that couldn't be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// Error returned by `Sender::try_send`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// The channel is full.
+    Full(T),
+    /// All receivers have been dropped.
+    Closed(T),
+}
+
+/// Error returned by `Receiver::recv` when the channel is empty and all senders have
+/// been dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+/// Error returned by `Receiver::try_recv`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// The channel is empty.
+    Empty,
+    /// The channel is empty and all senders have been dropped.
+    Closed,
+}
+
+struct State {
+    head: usize,
+    len: usize,
+    senders: usize,
+    receivers: usize,
+    send_waiters: WaitQueue,
+    recv_waiters: WaitQueue,
+}
+
+/// The channel shared by all endpoints, the buffer is unsized to hide the capacity from
+/// the endpoint types.
+struct Shared<T, B: ?Sized = [UnsafeCell<T>]> {
+    state: Lock<State>,
+    _value: PhantomData<T>,
+    buffer: B,
+}
+
+unsafe impl<T: Send, B: ?Sized> Sync for Shared<T, B> {}
+
+impl<T> Shared<T> {
+    fn notify_all(&self, queue: fn(&mut State) -> &mut WaitQueue) {
+        while let Some(waker) = self.state.lock(|state| queue(state).notify_one()) {
+            waker.wake();
+        }
+    }
+
+    fn notify_one(&self, queue: fn(&mut State) -> &mut WaitQueue) {
+        if let Some(waker) = self.state.lock(|state| queue(state).notify_one()) {
+            waker.wake();
+        }
+    }
+}
+
+impl<T: Clone> Shared<T> {
+    /// Send `value`, queueing `waiter` if the channel is full.
+    fn send_or_wait(&self, value: T, waiter: Option<(&Waiter, &Waker)>) -> Result<(), TrySendError<T>> {
+        let result = self.state.lock(|state| unsafe {
+            if state.receivers > 0 && state.len == self.buffer.len() {
+                if let Some((waiter, waker)) = waiter {
+                    waiter.wait(&mut state.send_waiters, waker);
+                }
+                return Err(TrySendError::Full(value));
+            }
+            if let Some((waiter, _)) = waiter {
+                waiter.cancel(&mut state.send_waiters);
+            }
+            if state.receivers == 0 {
+                return Err(TrySendError::Closed(value));
+            }
+
+            let index = (state.head + state.len) % self.buffer.len();
+            state.len += 1;
+            let previous = core::mem::replace(&mut *self.buffer[index].get(), value);
+            Ok((previous, state.recv_waiters.notify_one()))
+        });
+
+        result.map(|(_previous, waker)| {
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        })
+    }
+
+    /// Receive a value, queueing `waiter` if the channel is empty.
+    fn recv_or_wait(&self, waiter: Option<(&Waiter, &Waker)>) -> Result<T, TryRecvError> {
+        let result = self.state.lock(|state| unsafe {
+            if state.senders > 0 && state.len == 0 {
+                if let Some((waiter, waker)) = waiter {
+                    waiter.wait(&mut state.recv_waiters, waker);
+                }
+                return Err(TryRecvError::Empty);
+            }
+            if let Some((waiter, _)) = waiter {
+                waiter.cancel(&mut state.recv_waiters);
+            }
+            if state.len == 0 {
+                return Err(TryRecvError::Closed);
+            }
+
+            let value = (*self.buffer[state.head].get()).clone();
+            state.head = (state.head + 1) % self.buffer.len();
+            state.len -= 1;
+            Ok((value, state.send_waiters.notify_one()))
+        });
+
+        result.map(|(value, waker)| {
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+            value
+        })
+    }
+}
+
+/// A bounded multi-producer, multi-consumer channel with inline storage for `N` values.
+///
+/// The channel is usually created with the `channel!` macro, and used through the
+/// `Sender` and `Receiver` endpoints returned by `split()`. Endpoints can be cloned. When
+/// all senders have been dropped, receivers get `Err(RecvError)` once the channel is empty.
+/// When all receivers have been dropped, sending fails and returns the value.
+///
+/// Values are cloned out of their slot when received, a slot keeps its value until it's
+/// overwritten by a later send.
+///
+/// `try_send` and `try_recv` never wait, and are safe from interrupt handlers, see
+/// `critical_section`.
+///
+/// ## Example
+///
+/// ```
+/// use uio::sync::{Receiver, Sender, TrySendError};
+///
+/// async fn producer(sender: Sender<'_, u32>) {
+///     for value in 0..5 {
+///         sender.send(value).await.unwrap();
+///     }
+/// }
+///
+/// async fn consumer(receiver: Receiver<'_, u32>) -> u32 {
+///     let mut sum = 0;
+///     while let Ok(value) = receiver.recv().await {
+///         sum += value;
+///     }
+///     sum
+/// }
+///
+/// fn main() {
+///     uio::channel!(numbers, 0u32, 2);
+///     let (sender, receiver) = numbers.split();
+///     assert_eq!(sender.try_send(10), Ok(()));
+///     assert_eq!(sender.try_send(20), Ok(()));
+///     assert_eq!(sender.try_send(30), Err(TrySendError::Full(30)));
+///
+///     // The tasks borrow the channel, so they're started in a scope.
+///     uio::task_decl!(producer, producer(sender));
+///     uio::task_decl!(consumer, consumer(receiver));
+///     let sum = uio::executor::scope(|s| {
+///         s.start(producer);
+///         uio::executor::run_until(s.start(consumer))
+///     });
+///     assert_eq!(sum, Ok(40));
+/// }
+/// ```
+pub struct Channel<T, const N: usize> {
+    shared: Shared<T, [UnsafeCell<T>; N]>,
+}
+
+impl<T: Clone, const N: usize> Channel<T, N> {
+    /// Create a new channel with every slot initialized to a clone of `init`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is zero.
+    pub fn new(init: T) -> Self {
+        assert!(N > 0, "Channel capacity must be greater than zero");
+        Self {
+            shared: Shared {
+                state: Lock::new(State {
+                    head: 0,
+                    len: 0,
+                    senders: 0,
+                    receivers: 0,
+                    send_waiters: WaitQueue::new(),
+                    recv_waiters: WaitQueue::new(),
+                }),
+                _value: PhantomData,
+                buffer: core::array::from_fn(|_| UnsafeCell::new(init.clone())),
+            },
+        }
+    }
+}
+
+impl<T, const N: usize> Channel<T, N> {
+    /// Get a sender and a receiver to the channel.
+    ///
+    /// The channel is emptied, since all endpoints from an earlier split have been dropped.
+    pub fn split(&mut self) -> (Sender<'_, T>, Receiver<'_, T>) {
+        self.shared.state.lock(|state| {
+            state.head = 0;
+            state.len = 0;
+            state.senders = 1;
+            state.receivers = 1;
+        });
+        let shared: &Shared<T> = &self.shared;
+        (Sender { shared }, Receiver { shared })
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+}
+
+/// Create a channel named `$name` with capacity for `$size` values, with every slot
+/// initialized to `$init`.
+///
+/// The channel is stored inline, in the scope the macro is used in.
+#[macro_export]
+macro_rules! channel {
+    ($name:ident, $init:expr, $size:expr) => {
+        let mut $name = $crate::sync::Channel::<_, { $size }>::new($init);
+    };
+}
+
+/// The sending half of a channel.
+pub struct Sender<'a, T> {
+    shared: &'a Shared<T>,
+}
+
+impl<'a, T: Clone> Sender<'a, T> {
+    /// Send `value`, waiting while the channel is full.
+    ///
+    /// Resolves to `Err(SendError(value))` if all receivers have been dropped.
+    pub fn send(&self, value: T) -> SendFuture<'_, T> {
+        SendFuture {
+            shared: self.shared,
+            value: Some(value),
+            waiter: Waiter::new(),
+        }
+    }
+
+    /// Try to send `value` without waiting.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.shared.send_or_wait(value, None)
+    }
+}
+
+impl<'a, T> Clone for Sender<'a, T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock(|state| state.senders += 1);
+        Self { shared: self.shared }
+    }
+}
+
+impl<'a, T> Drop for Sender<'a, T> {
+    fn drop(&mut self) {
+        let closed = self.shared.state.lock(|state| {
+            state.senders -= 1;
+            state.senders == 0
+        });
+        if closed {
+            self.shared.notify_all(|state| &mut state.recv_waiters);
+        }
+    }
+}
+
+/// The receiving half of a channel.
+pub struct Receiver<'a, T> {
+    shared: &'a Shared<T>,
+}
+
+impl<'a, T: Clone> Receiver<'a, T> {
+    /// Receive a value, waiting while the channel is empty.
+    ///
+    /// Resolves to `Err(RecvError)` if the channel is empty and all senders have been dropped.
+    pub fn recv(&self) -> RecvFuture<'_, T> {
+        RecvFuture {
+            shared: self.shared,
+            waiter: Waiter::new(),
+        }
+    }
+
+    /// Try to receive a value without waiting.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.shared.recv_or_wait(None)
+    }
+}
+
+impl<'a, T> Clone for Receiver<'a, T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock(|state| state.receivers += 1);
+        Self { shared: self.shared }
+    }
+}
+
+impl<'a, T> Drop for Receiver<'a, T> {
+    fn drop(&mut self) {
+        let closed = self.shared.state.lock(|state| {
+            state.receivers -= 1;
+            state.receivers == 0
+        });
+        if closed {
+            self.shared.notify_all(|state| &mut state.send_waiters);
+        }
+    }
+}
+
+/// Future returned by `Sender::send`.
+pub struct SendFuture<'a, T> {
+    shared: &'a Shared<T>,
+    value: Option<T>,
+    waiter: Waiter,
+}
+
+impl<'a, T: Clone> Future for SendFuture<'a, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The waiter is never moved out of the pinned future.
+        let this = unsafe { self.get_unchecked_mut() };
+        let value = this.value.take().expect("SendFuture polled after completion");
+        match this.shared.send_or_wait(value, Some((&this.waiter, cx.waker()))) {
+            Ok(()) => Poll::Ready(Ok(())),
+            Err(TrySendError::Closed(value)) => Poll::Ready(Err(SendError(value))),
+            Err(TrySendError::Full(value)) => {
+                this.value = Some(value);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<'a, T> Drop for SendFuture<'a, T> {
+    fn drop(&mut self) {
+        let waiter = &self.waiter;
+        if self.shared.state.lock(|state| unsafe { waiter.cancel(&mut state.send_waiters) }) {
+            self.shared.notify_one(|state| &mut state.send_waiters);
+        }
+    }
+}
+
+/// Future returned by `Receiver::recv`.
+pub struct RecvFuture<'a, T> {
+    shared: &'a Shared<T>,
+    waiter: Waiter,
+}
+
+impl<'a, T: Clone> Future for RecvFuture<'a, T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The waiter is never moved out of the pinned future.
+        let this = unsafe { self.get_unchecked_mut() };
+        match this.shared.recv_or_wait(Some((&this.waiter, cx.waker()))) {
+            Ok(value) => Poll::Ready(Ok(value)),
+            Err(TryRecvError::Closed) => Poll::Ready(Err(RecvError)),
+            Err(TryRecvError::Empty) => Poll::Pending,
+        }
+    }
+}
+
+impl<'a, T> Drop for RecvFuture<'a, T> {
+    fn drop(&mut self) {
+        let waiter = &self.waiter;
+        if self.shared.state.lock(|state| unsafe { waiter.cancel(&mut state.recv_waiters) }) {
+            self.shared.notify_one(|state| &mut state.recv_waiters);
+        }
+    }
+}
@@ -0,0 +1,16 @@
+//! Synchronization primitives for tasks
+//!
+//! All primitives keep their waiting futures in intrusive, allocation-free FIFO queues,
+//! with the queue nodes embedded in the futures themselves. Dropping a waiting future
+//! removes it from its queue.
+
+mod channel;
+mod mutex;
+mod semaphore;
+mod signal;
+mod wait_queue;
+
+pub use channel::{Channel, Receiver, RecvError, RecvFuture, SendError, SendFuture, Sender, TryRecvError, TrySendError};
+pub use mutex::{LockFuture, Mutex, MutexGuard};
+pub use semaphore::{AcquireFuture, Permit, Semaphore};
+pub use signal::{Signal, WaitFuture};
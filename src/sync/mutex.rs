@@ -0,0 +1,236 @@
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use super::wait_queue::{WaitQueue, Waiter};
+use crate::lock::Lock;
+
+struct State {
+    locked: bool,
+    waiters: WaitQueue,
+}
+
+impl State {
+    /// Hand the lock over to the first waiter, or unlock if no task is waiting.
+    fn unlock(&mut self) -> Option<Waker> {
+        let waker = self.waiters.notify_one();
+        if waker.is_none() {
+            self.locked = false;
+        }
+        waker
+    }
+}
+
+/// An async mutex, handing the lock over to waiting tasks in FIFO order.
+///
+/// Tasks waiting in `lock()` are kept in an intrusive queue inside their lock futures.
+/// Dropping a waiting lock future removes it from the queue, and if the lock had already
+/// been handed to it, the lock is passed on to the next waiter.
+///
+/// `try_lock()` never waits, and is safe from interrupt handlers, see `critical_section`.
+///
+/// ## Example
+///
+/// ```
+/// use uio::executor::yield_now;
+/// use uio::sync::Mutex;
+///
+/// static LOG: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+///
+/// async fn worker(id: u32) {
+///     let mut log = LOG.lock().await;
+///     log.push(id);
+///     yield_now().await;
+///     log.push(id);
+/// }
+///
+/// fn main() {
+///     uio::task_start!(worker1, worker(1));
+///     uio::task_start!(worker2, worker(2));
+///     uio::task_start!(worker3, worker(3));
+///     uio::executor::run();
+///     assert_eq!(*LOG.try_lock().unwrap(), [1, 1, 2, 2, 3, 3]);
+/// }
+/// ```
+pub struct Mutex<T> {
+    state: Lock<State>,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    /// Create a new, unlocked, mutex protecting `value`.
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: Lock::new(State {
+                locked: false,
+                waiters: WaitQueue::new(),
+            }),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Lock the mutex, waiting until it's available.
+    ///
+    /// Dropping the returned future gives up the place in the queue.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use uio::sync::Mutex;
+    /// use uio::time::{with_timeout, Duration, MockClock, Timer};
+    ///
+    /// static CLOCK: MockClock = MockClock::new();
+    /// static BUS: Mutex<u32> = Mutex::new(0);
+    ///
+    /// async fn hold(bus: &Mutex<u32>) {
+    ///     let _bus = bus.lock().await;
+    ///     Timer::after(Duration::from_millis(20)).await;
+    /// }
+    ///
+    /// async fn impatient(bus: &Mutex<u32>) {
+    ///     assert!(with_timeout(Duration::from_millis(10), bus.lock()).await.is_err());
+    /// }
+    ///
+    /// async fn patient(bus: &Mutex<u32>) {
+    ///     *bus.lock().await += 1;
+    /// }
+    ///
+    /// fn main() {
+    ///     unsafe { uio::time::set_clock(&CLOCK) };
+    ///     uio::task_start!(holder, hold(&BUS));
+    ///     uio::task_start!(first, impatient(&BUS));
+    ///     uio::task_start!(second, patient(&BUS));
+    ///     uio::executor::run();
+    ///     assert_eq!(*BUS.try_lock().unwrap(), 1);
+    /// }
+    /// ```
+    pub fn lock(&self) -> LockFuture<'_, T> {
+        LockFuture {
+            mutex: self,
+            waiter: Waiter::new(),
+        }
+    }
+
+    /// Try to lock the mutex without waiting.
+    ///
+    /// Returns `None` if the mutex is locked.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let locked = self.state.lock(|state| core::mem::replace(&mut state.locked, true));
+        if locked {
+            None
+        } else {
+            Some(MutexGuard::new(self))
+        }
+    }
+
+    /// Get a mutable reference to the protected value, no locking is needed since the
+    /// mutex is mutably borrowed.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    fn unlock(&self) {
+        if let Some(waker) = self.state.lock(State::unlock) {
+            waker.wake();
+        }
+    }
+}
+
+/// Future returned by `Mutex::lock`.
+pub struct LockFuture<'a, T> {
+    mutex: &'a Mutex<T>,
+    waiter: Waiter,
+}
+
+impl<'a, T> Future for LockFuture<'a, T> {
+    type Output = MutexGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The waiter is never moved out of the pinned future.
+        let this = unsafe { self.get_unchecked_mut() };
+        let waiter = &this.waiter;
+        let acquired = this.mutex.state.lock(|state| unsafe {
+            if waiter.take_notified() {
+                // The lock was handed over by the previous owner.
+                true
+            } else if !state.locked {
+                state.locked = true;
+                waiter.cancel(&mut state.waiters);
+                true
+            } else {
+                waiter.wait(&mut state.waiters, cx.waker());
+                false
+            }
+        });
+
+        if acquired {
+            Poll::Ready(MutexGuard::new(this.mutex))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<'a, T> Drop for LockFuture<'a, T> {
+    fn drop(&mut self) {
+        let waiter = &self.waiter;
+        let waker = self.mutex.state.lock(|state| unsafe {
+            if waiter.cancel(&mut state.waiters) {
+                // The lock was handed over but never taken, pass it on.
+                state.unlock()
+            } else {
+                None
+            }
+        });
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Guard giving access to the value protected by a `Mutex`.
+///
+/// Dropping the guard unlocks the mutex, handing it over to the next waiting task.
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+    _value: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> MutexGuard<'a, T> {
+    fn new(mutex: &'a Mutex<T>) -> Self {
+        Self {
+            mutex,
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> Deref for MutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<'a, T> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<'a, T> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
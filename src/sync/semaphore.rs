@@ -0,0 +1,228 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use super::wait_queue::{WaitQueue, Waiter};
+use crate::lock::Lock;
+
+struct State {
+    permits: usize,
+    waiters: WaitQueue,
+}
+
+impl State {
+    /// Hand permits over to the first waiter if there are enough available.
+    fn grant_front(&mut self) -> Option<Waker> {
+        match self.waiters.front_value() {
+            Some(needed) if needed <= self.permits => {
+                self.permits -= needed;
+                self.waiters.notify_one()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A counting semaphore, handing permits over to waiting tasks in FIFO order.
+///
+/// A task waiting for more permits than are available blocks all tasks queued after it,
+/// even if they need fewer permits. Tasks waiting in `acquire()` are kept in an intrusive
+/// queue inside their acquire futures, dropping a waiting future removes it from the queue.
+///
+/// `try_acquire()` and `release()` never wait, and are safe from interrupt handlers, see
+/// `critical_section`.
+///
+/// ## Example
+///
+/// ```
+/// use core::sync::atomic::{AtomicUsize, Ordering};
+/// use uio::executor::yield_now;
+/// use uio::sync::Semaphore;
+///
+/// static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+/// static CHANNELS: Semaphore = Semaphore::new(2);
+///
+/// async fn transfer(channels: &Semaphore) {
+///     let _permit = channels.acquire(1).await;
+///     assert!(ACTIVE.fetch_add(1, Ordering::AcqRel) < 2);
+///     yield_now().await;
+///     ACTIVE.fetch_sub(1, Ordering::AcqRel);
+/// }
+///
+/// fn main() {
+///     uio::task_start!(transfer1, transfer(&CHANNELS));
+///     uio::task_start!(transfer2, transfer(&CHANNELS));
+///     uio::task_start!(transfer3, transfer(&CHANNELS));
+///     uio::task_start!(transfer4, transfer(&CHANNELS));
+///     uio::executor::run();
+///     assert_eq!(CHANNELS.available_permits(), 2);
+/// }
+/// ```
+pub struct Semaphore {
+    state: Lock<State>,
+}
+
+impl Semaphore {
+    /// Create a new semaphore with `permits` available permits.
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            state: Lock::new(State {
+                permits,
+                waiters: WaitQueue::new(),
+            }),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.state.lock(|state| state.permits)
+    }
+
+    /// Acquire `permits` permits, waiting until they're available.
+    ///
+    /// The permits are released when the returned `Permit` is dropped.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use std::sync::Mutex;
+    /// use uio::sync::Semaphore;
+    ///
+    /// static ORDER: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+    /// static SEMAPHORE: Semaphore = Semaphore::new(1);
+    ///
+    /// async fn acquire(semaphore: &Semaphore, permits: usize) {
+    ///     let _permit = semaphore.acquire(permits).await;
+    ///     ORDER.lock().unwrap().push(permits);
+    /// }
+    ///
+    /// fn main() {
+    ///     uio::task_start!(large, acquire(&SEMAPHORE, 2));
+    ///     // Queued after `large`, even though a permit is available.
+    ///     uio::task_start!(small, acquire(&SEMAPHORE, 1));
+    ///     uio::task_start!(releaser, async { SEMAPHORE.release(1) });
+    ///     uio::executor::run();
+    ///     assert_eq!(*ORDER.lock().unwrap(), [2, 1]);
+    /// }
+    /// ```
+    pub fn acquire(&self, permits: usize) -> AcquireFuture<'_> {
+        AcquireFuture {
+            semaphore: self,
+            permits,
+            waiter: Waiter::new(),
+        }
+    }
+
+    /// Try to acquire `permits` permits without waiting.
+    ///
+    /// Returns `None` if not enough permits are available, or if other tasks are waiting.
+    pub fn try_acquire(&self, permits: usize) -> Option<Permit<'_>> {
+        let acquired = self.state.lock(|state| {
+            if state.waiters.is_empty() && state.permits >= permits {
+                state.permits -= permits;
+                true
+            } else {
+                false
+            }
+        });
+        if acquired {
+            Some(Permit {
+                semaphore: self,
+                permits,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Add `permits` permits to the semaphore, waking waiting tasks that can now acquire
+    /// their permits.
+    pub fn release(&self, permits: usize) {
+        self.state.lock(|state| state.permits += permits);
+        self.wake_waiters();
+    }
+
+    fn wake_waiters(&self) {
+        while let Some(waker) = self.state.lock(State::grant_front) {
+            waker.wake();
+        }
+    }
+}
+
+/// Future returned by `Semaphore::acquire`.
+pub struct AcquireFuture<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+    waiter: Waiter,
+}
+
+impl<'a> Future for AcquireFuture<'a> {
+    type Output = Permit<'a>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The waiter is never moved out of the pinned future.
+        let this = unsafe { self.get_unchecked_mut() };
+        let waiter = &this.waiter;
+        let permits = this.permits;
+        let acquired = this.semaphore.state.lock(|state| unsafe {
+            if waiter.take_notified() {
+                // The permits were handed over by `release()`.
+                true
+            } else if state.waiters.is_empty() && state.permits >= permits {
+                state.permits -= permits;
+                true
+            } else {
+                waiter.wait_with(&mut state.waiters, cx.waker(), permits);
+                false
+            }
+        });
+
+        if acquired {
+            Poll::Ready(Permit {
+                semaphore: this.semaphore,
+                permits,
+            })
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<'a> Drop for AcquireFuture<'a> {
+    fn drop(&mut self) {
+        let waiter = &self.waiter;
+        let permits = self.permits;
+        self.semaphore.state.lock(|state| unsafe {
+            if waiter.cancel(&mut state.waiters) {
+                // The permits were handed over but never taken, give them back.
+                state.permits += permits;
+            }
+        });
+        // Waiters queued after this one might be able to acquire their permits now.
+        self.semaphore.wake_waiters();
+    }
+}
+
+/// Permits acquired from a `Semaphore`, released when dropped.
+pub struct Permit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl<'a> Permit<'a> {
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Drop the permit without releasing the permits to the semaphore.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.release(self.permits);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::interrupt::waker::Waker;
+use crate::lock::Lock;
+
+/// Single-slot notification, passing the latest value from an interrupt handler to a task.
+///
+/// Signalling overwrites any value that hasn't been taken yet. Only one task should wait
+/// on a signal at a time, a task calling `wait()` replaces the waker of an earlier waiter.
+///
+/// `signal()` never waits, and is safe from interrupt handlers, see `critical_section`.
+///
+/// ## Example
+///
+/// ```
+/// use uio::sync::Signal;
+///
+/// static SAMPLE: Signal<u32> = Signal::new();
+///
+/// fn main() {
+///     // A thread plays the part of the interrupt handler.
+///     std::thread::spawn(|| {
+///         SAMPLE.signal(1);
+///         SAMPLE.signal(2);
+///     })
+///     .join()
+///     .unwrap();
+///     assert_eq!(uio::executor::block_on(SAMPLE.wait()), 2);
+///
+///     std::thread::spawn(|| {
+///         std::thread::sleep(std::time::Duration::from_millis(10));
+///         SAMPLE.signal(3);
+///     });
+///     assert_eq!(uio::executor::block_on(SAMPLE.wait()), 3);
+///     assert_eq!(SAMPLE.try_take(), None);
+/// }
+/// ```
+///
+/// Signals from several contexts at once are never torn, and the last one is always seen:
+///
+/// ```
+/// use core::sync::atomic::{AtomicUsize, Ordering};
+/// use uio::executor::yield_now;
+/// use uio::sync::Signal;
+///
+/// static COUNTER: Signal<[u64; 32]> = Signal::new();
+/// static FINISHED: AtomicUsize = AtomicUsize::new(0);
+///
+/// fn main() {
+///     for source in 0..2 {
+///         std::thread::spawn(move || {
+///             for count in 1..=5_000 {
+///                 COUNTER.signal([count * 2 + source; 32]);
+///             }
+///             FINISHED.fetch_add(1, Ordering::AcqRel);
+///         });
+///     }
+///     let last = uio::executor::block_on(async {
+///         let mut last = [0; 2];
+///         loop {
+///             let finished = FINISHED.load(Ordering::Acquire) == 2;
+///             match COUNTER.try_take() {
+///                 Some(counts) => {
+///                     assert!(counts.iter().all(|count| *count == counts[0]));
+///                     let source = (counts[0] % 2) as usize;
+///                     assert!(counts[0] > last[source]);
+///                     last[source] = counts[0];
+///                 }
+///                 None if finished => return last,
+///                 None => yield_now().await,
+///             }
+///         }
+///     });
+///     assert!(last[0] == 10_000 || last[1] == 10_001);
+/// }
+/// ```
+pub struct Signal<T> {
+    value: Lock<Option<T>>,
+    waker: Waker,
+}
+
+impl<T> Signal<T> {
+    pub const fn new() -> Self {
+        Self {
+            value: Lock::new(None),
+            waker: Waker::new(),
+        }
+    }
+
+    /// Store `value`, replacing any value that hasn't been taken, and wake the waiting task.
+    pub fn signal(&self, value: T) {
+        let previous = self.value.lock(|slot| slot.replace(value));
+        // Drop the replaced value outside of the critical section.
+        drop(previous);
+        self.waker.wake();
+    }
+
+    /// Wait until a value is signalled, and take it.
+    pub fn wait(&self) -> WaitFuture<'_, T> {
+        WaitFuture { signal: self }
+    }
+
+    /// Take the signalled value, if any, without waiting.
+    pub fn try_take(&self) -> Option<T> {
+        self.value.lock(Option::take)
+    }
+
+    pub fn is_signaled(&self) -> bool {
+        self.value.lock(|slot| slot.is_some())
+    }
+}
+
+impl<T> Default for Signal<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future returned by `Signal::wait`.
+pub struct WaitFuture<'a, T> {
+    signal: &'a Signal<T>,
+}
+
+impl<'a, T> Future for WaitFuture<'a, T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Register before taking, a signal in between then wakes the task again.
+        self.signal.waker.register(cx.waker());
+        match self.signal.try_take() {
+            Some(value) => Poll::Ready(value),
+            None => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use core::cell::UnsafeCell;
+use core::marker::PhantomPinned;
+use core::task::Waker;
+
+struct WaitNode {
+    waker: Option<Waker>,
+    prev: *mut WaitNode,
+    next: *mut WaitNode,
+    queued: bool,
+    notified: bool,
+    /// Primitive specific value, such as the number of permits a waiter needs.
+    value: usize,
+}
+
+/// Intrusive FIFO list of waiting futures.
+///
+/// The queue and all its waiters must only be accessed while holding the lock protecting
+/// the queue.
+pub(crate) struct WaitQueue {
+    head: *mut WaitNode,
+    tail: *mut WaitNode,
+}
+
+unsafe impl Send for WaitQueue {}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            head: core::ptr::null_mut(),
+            tail: core::ptr::null_mut(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_null()
+    }
+
+    /// The value of the first waiter.
+    pub fn front_value(&self) -> Option<usize> {
+        unsafe { self.head.as_ref().map(|node| node.value) }
+    }
+
+    unsafe fn push_back(&mut self, node: *mut WaitNode) {
+        (*node).prev = self.tail;
+        (*node).next = core::ptr::null_mut();
+        (*node).queued = true;
+        if self.tail.is_null() {
+            self.head = node;
+        } else {
+            (*self.tail).next = node;
+        }
+        self.tail = node;
+    }
+
+    unsafe fn remove(&mut self, node: *mut WaitNode) {
+        let prev = (*node).prev;
+        let next = (*node).next;
+        if prev.is_null() {
+            self.head = next;
+        } else {
+            (*prev).next = next;
+        }
+        if next.is_null() {
+            self.tail = prev;
+        } else {
+            (*next).prev = prev;
+        }
+
+        (*node).prev = core::ptr::null_mut();
+        (*node).next = core::ptr::null_mut();
+        (*node).queued = false;
+    }
+
+    /// Remove the first waiter and mark it as notified, returning its waker.
+    ///
+    /// The waker should be woken after the lock has been released.
+    pub fn notify_one(&mut self) -> Option<Waker> {
+        unsafe {
+            let head = self.head;
+            if head.is_null() {
+                return None;
+            }
+            self.remove(head);
+            (*head).notified = true;
+            (*head).waker.take()
+        }
+    }
+}
+
+/// A node in a `WaitQueue`, embedded in the future that waits.
+///
+/// A waiter must not move while it is queued, futures embedding it must be `!Unpin` and
+/// call `cancel()` when dropped.
+pub(crate) struct Waiter {
+    node: UnsafeCell<WaitNode>,
+    _pin: PhantomPinned,
+}
+
+unsafe impl Send for Waiter {}
+
+impl Waiter {
+    pub const fn new() -> Self {
+        Self {
+            node: UnsafeCell::new(WaitNode {
+                waker: None,
+                prev: core::ptr::null_mut(),
+                next: core::ptr::null_mut(),
+                queued: false,
+                notified: false,
+                value: 0,
+            }),
+            _pin: PhantomPinned,
+        }
+    }
+
+    /// Queue the waiter last in `queue`, or update its waker if it is already queued.
+    ///
+    /// Any earlier notification is cleared.
+    ///
+    /// # Safety
+    ///
+    /// The lock protecting `queue` must be held and the waiter must be pinned.
+    pub unsafe fn wait(&self, queue: &mut WaitQueue, waker: &Waker) {
+        self.wait_with(queue, waker, 0);
+    }
+
+    /// Like `wait()`, also setting the value of the waiter.
+    ///
+    /// # Safety
+    ///
+    /// See `wait()`.
+    pub unsafe fn wait_with(&self, queue: &mut WaitQueue, waker: &Waker, value: usize) {
+        let node = self.node.get();
+        match &(*node).waker {
+            Some(current) if current.will_wake(waker) => {}
+            _ => (*node).waker = Some(waker.clone()),
+        }
+        (*node).notified = false;
+        (*node).value = value;
+        if !(*node).queued {
+            queue.push_back(node);
+        }
+    }
+
+    /// Returns `true` if the waiter has been notified since it was last queued, clearing
+    /// the notification.
+    ///
+    /// # Safety
+    ///
+    /// The lock protecting the queue the waiter was queued in must be held.
+    pub unsafe fn take_notified(&self) -> bool {
+        core::mem::replace(&mut (*self.node.get()).notified, false)
+    }
+
+    /// Remove the waiter from `queue` if it is queued.
+    ///
+    /// Returns `true` if the waiter had been notified without consuming the notification,
+    /// the caller should then pass the notification on to the next waiter.
+    ///
+    /// # Safety
+    ///
+    /// The lock protecting `queue` must be held, and the waiter must only ever have been
+    /// queued in `queue`.
+    pub unsafe fn cancel(&self, queue: &mut WaitQueue) -> bool {
+        let node = self.node.get();
+        if (*node).queued {
+            queue.remove(node);
+        }
+        (*node).waker = None;
+        self.take_notified()
+    }
+}
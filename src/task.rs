@@ -2,108 +2,190 @@
 //!
 //! The task wraps a future and allows it to be started by the executor.
 
+use core::cell::UnsafeCell;
 use core::future::Future;
+use core::marker::PhantomPinned;
 use core::pin::Pin;
+use core::ptr::NonNull;
 use core::task::{Context, Poll};
-use core::sync::atomic::{AtomicU8, Ordering};
+use core::sync::atomic::{AtomicPtr, AtomicU8, AtomicUsize, Ordering};
 use core::ops::{BitAnd, BitOr};
-use embedded_async::intrusive::intrusive_list::Node;
 
-struct PlaceholderTask;
-impl crate::executor::Task for PlaceholderTask {
-    fn waker(&self) -> &'static TaskWaker {
-        panic!("Attempting to use the placeholder task!");
-    }
+use crate::executor::Cancelled;
 
-    fn node(&mut self) -> &mut Node<*mut dyn crate::executor::Task> {
-        panic!("Attempting to use the placeholder task!");
-    }
+const READY_FLAG: u8 = 0b0000_0001;
+const RUNNING_FLAG: u8 = 0b0000_0010;
+const REFERENCED_FLAG: u8 = 0b0000_0100;
+const CANCEL_FLAG: u8 = 0b0000_1000;
+const CLAIMED_FLAG: u8 = 0b0001_0000;
+const DROP_FLAG: u8 = 0b0010_0000;
 
-    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
-        panic!("Attempting to use the placeholder task!");
-    }
+pub struct TaskWaker {
+    ready_flag: AtomicU8,
+    /// Link used by the executors ready queue.
+    pub(crate) next_ready: AtomicPtr<TaskWaker>,
+    task: UnsafeCell<Option<NonNull<dyn crate::executor::Task>>>,
+    executor: AtomicPtr<crate::executor::Executor>,
+    priority: AtomicU8,
+    /// Live task counter of the `executor::Scope` the task was started in, if any.
+    scope: AtomicPtr<AtomicUsize>,
 }
 
-static mut PLACEHOLDER_TASK: PlaceholderTask = PlaceholderTask;
+unsafe impl Sync for TaskWaker {}
 
-pub struct TaskWaker {
-    ready_flag: AtomicU8,
+impl Default for TaskWaker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TaskWaker {
     pub const fn new() -> Self {
         Self {
-            ready_flag: AtomicU8::new(0)
+            ready_flag: AtomicU8::new(0),
+            next_ready: AtomicPtr::new(core::ptr::null_mut()),
+            task: UnsafeCell::new(None),
+            executor: AtomicPtr::new(core::ptr::null_mut()),
+            priority: AtomicU8::new(0),
+            scope: AtomicPtr::new(core::ptr::null_mut()),
         }
     }
 
     fn update_flag(&self, update_fn: impl Fn(u8) -> u8) -> u8 {
-        let mut flag_value = self.ready_flag.load(Ordering::Acquire);
-        let mut new_value = update_fn(flag_value);
-        while self.ready_flag.compare_and_swap(flag_value, new_value, Ordering::SeqCst) != flag_value {
-            flag_value = self.ready_flag.load(Ordering::Acquire);
-            new_value = update_fn(flag_value);
-        }
+        crate::critical_section::update(&self.ready_flag, update_fn)
+    }
+
+    /// Set the task owning this waker, its priority and the executor it is started on.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called by the executor when the task is started.
+    pub(crate) unsafe fn set_task(&self, task: *mut dyn crate::executor::Task, priority: u8, executor: &'static crate::executor::Executor) {
+        *self.task.get() = NonNull::new(task);
+        self.priority.store(priority, Ordering::Release);
+        self.executor.store(executor as *const _ as *mut _, Ordering::Release);
+    }
+
+    /// Decrement `live_tasks` when the task finishes.
+    pub(crate) fn set_scope(&self, live_tasks: &AtomicUsize) {
+        crate::critical_section::store(&self.scope, live_tasks as *const AtomicUsize as *mut AtomicUsize);
+    }
 
-        flag_value
+    /// Take the live task counter set by `set_scope`.
+    pub(crate) fn take_scope(&self) -> Option<&AtomicUsize> {
+        unsafe { crate::critical_section::swap(&self.scope, core::ptr::null_mut()).as_ref() }
+    }
+
+    pub(crate) fn priority(&self) -> usize {
+        self.priority.load(Ordering::Acquire) as usize
+    }
+
+    pub(crate) fn executor(&self) -> Option<&'static crate::executor::Executor> {
+        unsafe { self.executor.load(Ordering::Acquire).as_ref() }
+    }
+
+    pub(crate) fn task(&self) -> Option<NonNull<dyn crate::executor::Task>> {
+        unsafe { *self.task.get() }
     }
 
     pub(crate) fn is_finished(&self) -> bool {
-        (self.ready_flag.load(Ordering::Acquire) & 0x02) == 0
+        (self.ready_flag.load(Ordering::Acquire) & RUNNING_FLAG) == 0
     }
 
     pub(crate) fn set_started(&self) {
-        self.update_flag(|value| value.bitor(0b0000_0010));
+        self.update_flag(|value| value.bitor(RUNNING_FLAG).bitand(!CANCEL_FLAG));
     }
 
     pub(crate) fn set_finished(&self) {
-        self.update_flag(|value| value.bitand(0b1111_1101));
+        self.update_flag(|value| value.bitand(!RUNNING_FLAG));
     }
 
-    pub(crate) fn is_ready_to_poll(&self) -> bool {
-        (self.ready_flag.load(Ordering::Acquire) & 0x01) > 0
+    /// Mark a running task as ready to poll.
+    ///
+    /// Returns `true` if the flag was set by this call, in which case the waker must be
+    /// pushed to the ready queue.
+    pub(crate) fn set_ready_to_poll(&self) -> bool {
+        let previous = self.update_flag(|value| {
+            if value & RUNNING_FLAG != 0 {
+                value.bitor(READY_FLAG)
+            } else {
+                value
+            }
+        });
+        previous & (READY_FLAG | RUNNING_FLAG) == RUNNING_FLAG
     }
 
-    pub(crate) fn set_ready_to_poll(&self) {
-        self.update_flag(|value| value.bitor(0x01));
+    pub(crate) fn clear_ready_to_poll(&self) {
+        self.update_flag(|value| value.bitand(!READY_FLAG));
     }
 
-    pub(crate) fn clear_ready_to_poll(&self) {
-        self.update_flag(|value| value.bitand(0b1111_1110));
+    /// Request a running task to be cancelled.
+    ///
+    /// Returns `true` if the task is running, in which case it must be woken for the
+    /// executor to cancel it.
+    pub(crate) fn request_cancel(&self) -> bool {
+        let previous = self.update_flag(|value| {
+            if value & RUNNING_FLAG != 0 {
+                value.bitor(CANCEL_FLAG)
+            } else {
+                value
+            }
+        });
+        previous & RUNNING_FLAG != 0
     }
 
-    pub(crate) fn try_take_reference(&self) -> bool {
-        let mut flag_value = self.ready_flag.load(Ordering::Acquire);
-        if flag_value & 0b0000_0100 != 0 {
-            return false;
-        }
-        let mut new_value = flag_value | 0b0000_0100;
-        while self.ready_flag.compare_and_swap(flag_value, new_value, Ordering::SeqCst) != flag_value {
-            flag_value = self.ready_flag.load(Ordering::Acquire);
-            if flag_value & 0b0000_0100 != 0 {
-                return false;
+    pub(crate) fn is_cancel_requested(&self) -> bool {
+        (self.ready_flag.load(Ordering::Acquire) & CANCEL_FLAG) != 0
+    }
+
+    /// Claim exclusive access to the task for polling it.
+    ///
+    /// Returns `None` if the task is claimed, or is being dropped.
+    pub(crate) fn try_claim(&'static self) -> Option<TaskClaim> {
+        let previous = self.update_flag(|value| {
+            if value & (CLAIMED_FLAG | DROP_FLAG) == 0 {
+                value.bitor(CLAIMED_FLAG)
+            } else {
+                value
             }
-            new_value = flag_value | 0b0000_0100;
+        });
+        if previous & (CLAIMED_FLAG | DROP_FLAG) == 0 {
+            Some(TaskClaim { waker: self, flags: CLAIMED_FLAG })
+        } else {
+            None
         }
-        return true;
     }
 
-    pub(crate) fn release_reference(&self) -> bool {
-        let mut flag_value = self.ready_flag.load(Ordering::Acquire);
-        if flag_value & 0b0000_0100 == 0 {
-            return false;
+    /// Claim exclusive access to the task for dropping it, waiting for the executor to
+    /// finish polling it.
+    pub(crate) fn claim_for_drop(&'static self) -> TaskClaim {
+        // Once the drop flag is set the executor no longer claims the task.
+        self.update_flag(|value| value.bitor(DROP_FLAG));
+        while self.update_flag(|value| value.bitor(CLAIMED_FLAG)) & CLAIMED_FLAG != 0 {
+            core::hint::spin_loop();
         }
+        TaskClaim { waker: self, flags: CLAIMED_FLAG | DROP_FLAG }
+    }
 
-        let mut new_value = flag_value & !0b0000_0100;
+    pub(crate) fn try_take_reference(&self) -> bool {
+        self.update_flag(|value| value.bitor(REFERENCED_FLAG)) & REFERENCED_FLAG == 0
+    }
 
-        while self.ready_flag.compare_and_swap(flag_value, new_value, Ordering::SeqCst) != flag_value {
-            flag_value = self.ready_flag.load(Ordering::Acquire);
-            if flag_value & 0b0000_0100 == 0 {
-                return false;
-            }
-            new_value = flag_value & !0b0000_0100;
-        }
-        return true;
+    pub(crate) fn release_reference(&self) -> bool {
+        self.update_flag(|value| value.bitand(!REFERENCED_FLAG)) & REFERENCED_FLAG != 0
+    }
+}
+
+/// Exclusive access to a task, released when dropped.
+pub(crate) struct TaskClaim {
+    waker: &'static TaskWaker,
+    flags: u8,
+}
+
+impl Drop for TaskClaim {
+    fn drop(&mut self) {
+        let flags = self.flags;
+        self.waker.update_flag(|value| value.bitand(!flags));
     }
 }
 
@@ -113,6 +195,11 @@ macro_rules! task_decl {
         let $name = uio::task::Task::new($val, { static WAKER: uio::task::TaskWaker = uio::task::TaskWaker::new(); &WAKER});
         $crate::pin_utils::pin_mut!($name);
     };
+    ($name:ident, $val:expr, priority = $priority:expr) => {
+        let $name = uio::task::Task::new($val, { static WAKER: uio::task::TaskWaker = uio::task::TaskWaker::new(); &WAKER})
+            .with_priority($priority);
+        $crate::pin_utils::pin_mut!($name);
+    };
 }
 
 #[macro_export]
@@ -121,6 +208,10 @@ macro_rules! task_start {
         $crate::task_decl!($name, $val);
         let $name = uio::executor::start($name);
     };
+    ($name:ident, $val:expr, priority = $priority:expr) => {
+        $crate::task_decl!($name, $val, priority = $priority);
+        let $name = uio::executor::start($name);
+    };
 }
 
 struct TaskData {
@@ -143,58 +234,208 @@ impl Drop for TaskData {
         if !self.waker.release_reference() {
             panic!("Releasing an already released reference!");
         }
-        if !self.waker.is_finished() {
-            panic!("Task dropped while it is still running");
-        }
     }
 }
 
 /// Task structure, wrapping a future allowing it to be run by the executor.
+///
+/// Dropping a task that is still running cancels it: the future is dropped in place and
+/// the task is removed from its executor. This lets tasks started within an async function
+/// act as scoped children of that function. Enable the `strict-task-drop` feature to
+/// instead panic in debug builds when a running task is dropped.
+///
+/// Dropping a task waits for its executor to finish polling it, if the executor runs on
+/// another thread or in an interrupt handler. A task must therefore never be dropped from an
+/// interrupt handler that preempted the executor of the task.
+///
+/// ## Example
+///
+/// ```
+/// use uio::executor::yield_now;
+///
+/// async fn forever() {
+///     loop {
+///         yield_now().await;
+///     }
+/// }
+///
+/// async fn parent() -> u32 {
+///     uio::task_start!(_child, forever());
+///     yield_now().await;
+///     // `_child` is cancelled when it goes out of scope.
+///     5
+/// }
+///
+/// fn main() {
+///     assert_eq!(uio::executor::block_on(parent()), 5);
+///     uio::executor::run();
+/// }
+/// ```
+///
+/// Dropping a task polled by an executor on another thread waits for the poll to finish:
+///
+/// ```
+/// use core::future::Future;
+/// use core::pin::Pin;
+/// use core::sync::atomic::{AtomicBool, Ordering};
+/// use core::task::{Context, Poll};
+/// use std::time::Duration;
+/// use uio::executor::{yield_now, Executor};
+///
+/// static EXECUTOR: Executor = Executor::new();
+/// static POLLING: AtomicBool = AtomicBool::new(false);
+/// static DONE: AtomicBool = AtomicBool::new(false);
+///
+/// struct SlowPoll;
+///
+/// impl Future for SlowPoll {
+///     type Output = ();
+///     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+///         POLLING.store(true, Ordering::Release);
+///         std::thread::sleep(Duration::from_millis(20));
+///         POLLING.store(false, Ordering::Release);
+///         cx.waker().wake_by_ref();
+///         Poll::Pending
+///     }
+/// }
+///
+/// impl Drop for SlowPoll {
+///     fn drop(&mut self) {
+///         assert!(!POLLING.load(Ordering::Acquire));
+///     }
+/// }
+///
+/// fn main() {
+///     let executor = std::thread::spawn(|| {
+///         EXECUTOR.block_on(async {
+///             while !DONE.load(Ordering::Acquire) {
+///                 yield_now().await;
+///             }
+///         })
+///     });
+///     {
+///         uio::task_decl!(task, SlowPoll);
+///         let _ = EXECUTOR.start(task);
+///         while !POLLING.load(Ordering::Acquire) {
+///             std::thread::yield_now();
+///         }
+///     }
+///     DONE.store(true, Ordering::Release);
+///     executor.join().unwrap();
+/// }
+/// ```
 pub struct Task<T: Future> {
-    future: T,
+    future: Option<T>,
     task_data: TaskData,
-    list_node: embedded_async::intrusive::intrusive_list::Node<*mut dyn crate::executor::Task>,
-    value: crate::future::Value<T::Output>,
+    value: crate::future::Value<Result<T::Output, Cancelled>>,
+    priority: u8,
+    // Executors keep a pointer to the task, which relies on the task never being moved,
+    // or forgotten, once pinned.
+    _pinned: PhantomPinned,
 }
 
-impl<T: Future> Unpin for Task<T> {}
-
 impl<T: Future> Task<T> {
     /// Create a new task wrapping the specified `future`.
     pub fn new(future: T, waker: &'static TaskWaker) -> Self {
         Self {
-            future,
+            future: Some(future),
             task_data: TaskData::new(waker),
-            list_node: embedded_async::intrusive::intrusive_list::Node::new(unsafe { &mut PLACEHOLDER_TASK }),
             value: crate::future::Value::new(),
+            priority: 0,
+            _pinned: PhantomPinned,
         }
     }
+
+    /// Set the priority of the task, higher priority tasks are polled first.
+    ///
+    /// The default priority is 0, the lowest priority. Priorities above
+    /// `executor::PRIORITY_LEVELS - 1` are treated as the highest priority.
+    ///
+    /// The `task_decl!` and `task_start!` macros accept a trailing `priority = <value>`
+    /// argument that sets the priority.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// static ORDER: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// async fn record(expected: usize) {
+    ///     assert_eq!(ORDER.fetch_add(1, Ordering::AcqRel), expected);
+    /// }
+    ///
+    /// fn main() {
+    ///     uio::task_start!(low, record(1));
+    ///     uio::task_start!(high, record(0), priority = 3);
+    ///     uio::executor::run();
+    /// }
+    /// ```
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Take the output of the future, if it has finished and the output hasn't been taken yet.
+    pub(crate) fn take_output(self: Pin<&mut Self>) -> Option<T::Output> {
+        // The value is never pinned.
+        unsafe { self.get_unchecked_mut() }.value.take().and_then(Result::ok)
+    }
 }
 
-impl<T: Future> crate::executor::Task for Task<T> {
+unsafe impl<T: Future> crate::executor::Task for Task<T> {
     fn waker(&self) -> &'static TaskWaker {
-        &self.task_data.waker
+        self.task_data.waker
     }
 
-    fn node(&mut self) -> &mut Node<*mut dyn crate::executor::Task> {
-        &mut self.list_node
+    fn priority(&self) -> u8 {
+        self.priority
     }
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
-        let future = unsafe { Pin::new_unchecked(&mut self.future) };
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // The future is never moved out of the pinned task.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = this.future.as_mut().expect("Polling a cancelled task");
+        let future = unsafe { Pin::new_unchecked(future) };
         match future.poll(cx) {
             Poll::Pending => Poll::Pending,
             Poll::Ready(r) => {
-                self.value.set(r);
+                this.value.set(Ok(r));
                 Poll::Ready(())
             }
         }
     }
 }
 
-impl<T: Future> crate::executor::TypedTask for Task<T> {
+impl<T: Future> Drop for Task<T> {
+    fn drop(&mut self) {
+        let waker = self.task_data.waker;
+        // The executor claims the task while polling it, possibly on another thread.
+        let _claim = waker.claim_for_drop();
+        if !waker.is_finished() {
+            if cfg!(all(debug_assertions, feature = "strict-task-drop")) {
+                panic!("Task dropped while it is still running");
+            }
+            // Assigning drops the future in place, it is never moved.
+            self.future = None;
+            if let Some(executor) = waker.executor() {
+                executor.finish_task(waker);
+            }
+        }
+    }
+}
+
+impl<T: Future> crate::future::Cancelable for Task<T> {
+    fn cancel_future(&mut self) {
+        // Assigning drops the future in place, it is never moved.
+        self.future = None;
+        self.value.set(Err(Cancelled));
+    }
+}
+
+unsafe impl<T: Future> crate::executor::TypedTask for Task<T> {
     type Output = T::Output;
-    fn value_ptr(&mut self) -> *mut crate::future::Value<Self::Output> {
-        &mut self.value as *mut crate::future::Value<Self::Output>
+    fn value_ptr(&mut self) -> *mut crate::future::Value<Result<Self::Output, Cancelled>> {
+        &mut self.value as *mut crate::future::Value<Result<Self::Output, Cancelled>>
     }
 }
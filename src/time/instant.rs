@@ -0,0 +1,102 @@
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+
+use super::Duration;
+
+/// A measurement of the monotonic clock set with `time::set_clock`, with microsecond resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Instant {
+    micros: u64,
+}
+
+impl Instant {
+    /// The earliest possible instant, typically when the clock started.
+    pub const ZERO: Instant = Instant { micros: 0 };
+
+    pub const fn from_micros(micros: u64) -> Self {
+        Self { micros }
+    }
+
+    pub const fn from_millis(millis: u64) -> Self {
+        Self {
+            micros: millis * 1_000,
+        }
+    }
+
+    pub const fn as_micros(&self) -> u64 {
+        self.micros
+    }
+
+    pub const fn as_millis(&self) -> u64 {
+        self.micros / 1_000
+    }
+
+    /// The current time, read from the clock set with `time::set_clock`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no clock has been set.
+    pub fn now() -> Self {
+        super::clock().now()
+    }
+
+    /// The time elapsed since `earlier`, or zero if `earlier` is later than `self`.
+    pub fn saturating_duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_micros(self.micros.saturating_sub(earlier.micros))
+    }
+
+    /// The time elapsed since this instant.
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().saturating_duration_since(*self)
+    }
+
+    pub fn checked_add(self, duration: Duration) -> Option<Instant> {
+        self.micros
+            .checked_add(duration.as_micros())
+            .map(Instant::from_micros)
+    }
+
+    pub fn checked_sub(self, duration: Duration) -> Option<Instant> {
+        self.micros
+            .checked_sub(duration.as_micros())
+            .map(Instant::from_micros)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        self.checked_add(rhs).expect("Overflow when adding duration to instant")
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, rhs: Duration) -> Self::Output {
+        self.checked_sub(rhs)
+            .expect("Overflow when subtracting duration from instant")
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Self::Output {
+        Duration::from_micros(self.micros)
+            .checked_sub(Duration::from_micros(rhs.micros))
+            .expect("Subtracting a later instant")
+    }
+}
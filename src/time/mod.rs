@@ -0,0 +1,200 @@
+//! Time keeping and timers
+//!
+//! Time is read from a single monotonic `Clock`, set with `set_clock()` before any timer
+//! is used. `StdClock` reads the host clock, while `MockClock` provides virtual time for
+//! deterministic tests. Timers are kept in an intrusive, allocation-free queue that every executor
+//! services while running. When no task is ready, executors hand the nearest timer deadline
+//! to their idle strategy.
+//!
+//! Interrupt executors service the queue from their interrupt handler, see
+//! `critical_section`.
+
+mod duration;
+mod instant;
+mod ticker;
+mod timeout;
+mod timer;
+
+use crate::lock::Lock;
+
+pub use duration::Duration;
+pub use instant::Instant;
+pub use ticker::{MissedTickPolicy, Ticker};
+pub use timeout::{with_deadline, with_timeout, Timeout, TimeoutError};
+pub use timer::Timer;
+
+pub(crate) use timer::{expire_timers, next_deadline};
+
+/// Trait for monotonic time sources.
+pub trait Clock: Sync {
+    /// The current time, must never decrease.
+    fn now(&self) -> Instant;
+
+    /// Called by an executor that has no ready task, with the deadline of the next timer.
+    ///
+    /// Virtual clocks can jump to `deadline` and return `true`, in which case the executor
+    /// expires the timer instead of idling. The default implementation returns `false`.
+    fn advance_to(&self, _deadline: Instant) -> bool {
+        false
+    }
+}
+
+static mut CLOCK: Option<&'static dyn Clock> = None;
+
+/// Set the clock used by `Instant::now()` and all timers.
+///
+/// # Safety
+///
+/// The clock is read without synchronization by every executor and timer. This must be
+/// called before any timer is used, and never while another thread, executor or interrupt
+/// handler may read the time.
+///
+/// # Arguments
+///
+/// * `clock` - The clock to use.
+pub unsafe fn set_clock(clock: &'static dyn Clock) {
+    CLOCK = Some(clock);
+}
+
+fn clock() -> &'static dyn Clock {
+    unsafe { CLOCK }.expect("No clock set, call time::set_clock first")
+}
+
+pub(crate) fn advance_clock_to(deadline: Instant) -> bool {
+    clock().advance_to(deadline)
+}
+
+/// Virtual clock for deterministic tests.
+///
+/// Time only moves when `advance()` or `set()` is called, or when an executor with no ready
+/// task jumps to the deadline of the next timer. Timers therefore expire without any real
+/// waiting, and at exact virtual instants.
+///
+/// ## Example
+///
+/// ```
+/// use uio::time::{Duration, Instant, MockClock, Timer};
+///
+/// static CLOCK: MockClock = MockClock::new();
+///
+/// async fn sleeper(millis: u64) -> Instant {
+///     Timer::after(Duration::from_millis(millis)).await;
+///     Instant::now()
+/// }
+///
+/// async fn main_task() {
+///     uio::task_start!(short, sleeper(10));
+///     uio::task_start!(long, sleeper(250));
+///     assert_eq!(long.join().await, Ok(Instant::from_millis(250)));
+///     assert_eq!(short.join().await, Ok(Instant::from_millis(10)));
+/// }
+///
+/// fn main() {
+///     unsafe { uio::time::set_clock(&CLOCK) };
+///     uio::executor::block_on(main_task());
+///     CLOCK.advance(Duration::from_secs(1));
+///     assert_eq!(Instant::now(), Instant::from_millis(1250));
+/// }
+/// ```
+pub struct MockClock {
+    now: Lock<Instant>,
+}
+
+impl MockClock {
+    /// Create a new clock, starting at `Instant::ZERO`.
+    pub const fn new() -> Self {
+        Self {
+            now: Lock::new(Instant::ZERO),
+        }
+    }
+
+    /// Move the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.now.lock(|now| *now += duration);
+    }
+
+    /// Set the current time of the clock.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `instant` is earlier than the current time.
+    pub fn set(&self, instant: Instant) {
+        self.now.lock(|now| {
+            assert!(instant >= *now, "MockClock can't move backwards");
+            *now = instant;
+        });
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.now.lock(|now| *now)
+    }
+
+    fn advance_to(&self, deadline: Instant) -> bool {
+        self.now.lock(|now| {
+            if deadline > *now {
+                *now = deadline;
+            }
+        });
+        true
+    }
+}
+
+/// Clock based on `std::time::Instant`, starting at `Instant::ZERO` when first read.
+///
+/// ## Example
+///
+/// ```
+/// use uio::executor::idle::ThreadPark;
+/// use uio::time::{Duration, Instant, StdClock, Timer};
+///
+/// static CLOCK: StdClock = StdClock::new();
+/// static IDLE: ThreadPark = ThreadPark::new();
+///
+/// async fn sleeper() -> Duration {
+///     let start = Instant::now();
+///     Timer::after(Duration::from_millis(50)).await;
+///     start.elapsed()
+/// }
+///
+/// fn main() {
+///     unsafe { uio::time::set_clock(&CLOCK) };
+///     unsafe { uio::executor::set_idle_strategy(&IDLE) };
+///     assert!(uio::executor::block_on(sleeper()) >= Duration::from_millis(50));
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub struct StdClock {
+    start: std::sync::OnceLock<std::time::Instant>,
+}
+
+#[cfg(feature = "std")]
+impl StdClock {
+    pub const fn new() -> Self {
+        Self {
+            start: std::sync::OnceLock::new(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for StdClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for StdClock {
+    fn now(&self) -> Instant {
+        let start = self.start.get_or_init(std::time::Instant::now);
+        Instant::from_micros(start.elapsed().as_micros() as u64)
+    }
+}
@@ -0,0 +1,188 @@
+use super::{Duration, Instant, Timer};
+
+/// What a `Ticker` does when ticks were missed because a tick completed too late.
+///
+/// ## Example
+///
+/// ```
+/// use uio::time::{Duration, Instant, MissedTickPolicy, MockClock, Ticker};
+///
+/// static CLOCK: MockClock = MockClock::new();
+///
+/// async fn burst() {
+///     let mut ticker = Ticker::every(Duration::from_millis(10))
+///         .with_missed_tick_policy(MissedTickPolicy::Burst);
+///     assert_eq!(ticker.next().await, Instant::from_millis(10));
+///     assert_eq!(ticker.next().await, Instant::from_millis(20));
+///
+///     // The tick at 30ms is missed and completes immediately, along with the one at 40ms.
+///     CLOCK.advance(Duration::from_millis(25));
+///     assert_eq!(ticker.next().await, Instant::from_millis(30));
+///     assert_eq!(ticker.next().await, Instant::from_millis(40));
+///     assert_eq!(Instant::now(), Instant::from_millis(45));
+///     assert_eq!(ticker.overruns(), 1);
+///     assert_eq!(ticker.next().await, Instant::from_millis(50));
+///
+///     // One period late, the tick at 70ms is due now rather than missed.
+///     CLOCK.advance(Duration::from_millis(20));
+///     assert_eq!(ticker.next().await, Instant::from_millis(60));
+///     assert_eq!(ticker.next().await, Instant::from_millis(70));
+///     assert_eq!(Instant::now(), Instant::from_millis(70));
+///     assert_eq!(ticker.overruns(), 1);
+/// }
+///
+/// fn main() {
+///     unsafe { uio::time::set_clock(&CLOCK) };
+///     uio::executor::block_on(burst());
+/// }
+/// ```
+///
+/// ```
+/// use uio::time::{Duration, Instant, MissedTickPolicy, MockClock, Ticker};
+///
+/// static CLOCK: MockClock = MockClock::new();
+///
+/// async fn delay() {
+///     let mut ticker = Ticker::every(Duration::from_millis(10))
+///         .with_missed_tick_policy(MissedTickPolicy::Delay);
+///     assert_eq!(ticker.next().await, Instant::from_millis(10));
+///     assert_eq!(ticker.next().await, Instant::from_millis(20));
+///
+///     // The tick at 30ms completes at 45ms, the schedule restarts from there.
+///     CLOCK.advance(Duration::from_millis(25));
+///     assert_eq!(ticker.next().await, Instant::from_millis(30));
+///     assert_eq!(ticker.next().await, Instant::from_millis(55));
+///     assert_eq!(ticker.next().await, Instant::from_millis(65));
+///     assert_eq!(ticker.overruns(), 1);
+/// }
+///
+/// fn main() {
+///     unsafe { uio::time::set_clock(&CLOCK) };
+///     uio::executor::block_on(delay());
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissedTickPolicy {
+    /// Complete the missed ticks immediately, one after the other, until caught up.
+    Burst,
+    /// Skip the missed ticks, keeping the ticks aligned to the original schedule.
+    Skip,
+    /// Schedule the next tick one period after the late tick completed.
+    Delay,
+}
+
+/// Periodic timer, producing ticks at a fixed rate.
+///
+/// Deadlines are computed from the base instant and the period, so unlike chaining
+/// `Timer::after` calls the ticks never drift.
+///
+/// ## Example
+///
+/// ```
+/// use uio::time::{Duration, Instant, MissedTickPolicy, MockClock, Ticker};
+///
+/// static CLOCK: MockClock = MockClock::new();
+///
+/// async fn sampler() {
+///     let mut ticker = Ticker::every(Duration::from_millis(10))
+///         .with_missed_tick_policy(MissedTickPolicy::Skip);
+///     assert_eq!(ticker.next().await, Instant::from_millis(10));
+///     assert_eq!(ticker.next().await, Instant::from_millis(20));
+///
+///     // Simulate a slow sample, the tick at 40ms is missed.
+///     CLOCK.advance(Duration::from_millis(25));
+///     assert_eq!(ticker.next().await, Instant::from_millis(30));
+///     assert_eq!(ticker.next().await, Instant::from_millis(50));
+///     assert_eq!(ticker.overruns(), 1);
+///
+///     // One period late, the tick at 70ms is due now and isn't skipped.
+///     CLOCK.advance(Duration::from_millis(20));
+///     assert_eq!(ticker.next().await, Instant::from_millis(60));
+///     assert_eq!(ticker.next().await, Instant::from_millis(70));
+///     assert_eq!(ticker.overruns(), 1);
+/// }
+///
+/// fn main() {
+///     unsafe { uio::time::set_clock(&CLOCK) };
+///     uio::executor::block_on(sampler());
+/// }
+/// ```
+pub struct Ticker {
+    period: Duration,
+    next_deadline: Instant,
+    policy: MissedTickPolicy,
+    overruns: usize,
+}
+
+impl Ticker {
+    /// Create a ticker ticking every `period`, starting one period from now.
+    pub fn every(period: Duration) -> Self {
+        Self::starting_at(Instant::now(), period)
+    }
+
+    /// Create a ticker ticking every `period`, starting one period after `base`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn starting_at(base: Instant, period: Duration) -> Self {
+        assert!(period > Duration::ZERO, "Ticker period must be greater than zero");
+        Self {
+            period,
+            next_deadline: base + period,
+            policy: MissedTickPolicy::Burst,
+            overruns: 0,
+        }
+    }
+
+    /// Set the policy for missed ticks, the default policy is `MissedTickPolicy::Burst`.
+    pub fn with_missed_tick_policy(mut self, policy: MissedTickPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// The number of ticks that were missed because a tick completed too late.
+    ///
+    /// A tick due at the instant the previous tick completed isn't missed. With
+    /// `MissedTickPolicy::Burst` missed ticks are counted as they complete.
+    pub fn overruns(&self) -> usize {
+        self.overruns
+    }
+
+    /// Wait for the next tick, returning the instant the tick was scheduled at.
+    pub async fn next(&mut self) -> Instant {
+        let deadline = self.next_deadline;
+        Timer::at(deadline).await;
+        self.schedule_after(deadline, Instant::now());
+        deadline
+    }
+
+    fn schedule_after(&mut self, deadline: Instant, now: Instant) {
+        let next_deadline = deadline + self.period;
+        if next_deadline >= now {
+            self.next_deadline = next_deadline;
+            return;
+        }
+
+        // Ticks strictly before `now` were missed, a tick due at `now` is still on time.
+        let missed = ((now - deadline).as_micros() - 1) / self.period.as_micros();
+        match self.policy {
+            MissedTickPolicy::Burst => {
+                self.overruns += 1;
+                self.next_deadline = next_deadline;
+            }
+            MissedTickPolicy::Skip => {
+                self.overruns += missed as usize;
+                self.next_deadline = deadline + Duration::from_micros(self.period.as_micros() * (missed + 1));
+            }
+            MissedTickPolicy::Delay => {
+                self.overruns += missed as usize;
+                self.next_deadline = now + self.period;
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use super::{Duration, Instant, Timer};
+
+/// Error returned when a future didn't complete before its deadline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutError;
+
+/// Future returned by `with_timeout()` and `with_deadline()`.
+pub struct Timeout<F> {
+    future: Option<F>,
+    timer: Timer,
+}
+
+/// Run `future` for at most `duration`.
+///
+/// Resolves to the output of the future, or `Err(TimeoutError)` if `duration` passed
+/// before the future completed. On expiry the future is dropped in place.
+///
+/// ## Example
+///
+/// ```
+/// use uio::time::{with_timeout, Duration, Instant, MockClock, TimeoutError, Timer};
+///
+/// static CLOCK: MockClock = MockClock::new();
+///
+/// async fn main_task() {
+///     uio::task_start!(quick, async { 3 });
+///     assert_eq!(with_timeout(Duration::from_millis(10), quick.join()).await, Ok(Ok(3)));
+///
+///     uio::task_start!(slow, Timer::after(Duration::from_secs(1)));
+///     assert_eq!(with_timeout(Duration::from_millis(10), slow.join()).await, Err(TimeoutError));
+///     assert_eq!(Instant::now(), Instant::from_millis(10));
+/// }
+///
+/// fn main() {
+///     unsafe { uio::time::set_clock(&CLOCK) };
+///     uio::executor::block_on(main_task());
+/// }
+/// ```
+pub fn with_timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    with_deadline(Instant::now() + duration, future)
+}
+
+/// Run `future` until `deadline`.
+///
+/// Resolves to the output of the future, or `Err(TimeoutError)` if `deadline` was reached
+/// before the future completed. On expiry the future is dropped in place.
+pub fn with_deadline<F: Future>(deadline: Instant, future: F) -> Timeout<F> {
+    Timeout {
+        future: Some(future),
+        timer: Timer::at(deadline),
+    }
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, TimeoutError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Neither the future nor the timer is ever moved, the future is dropped in place.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = this.future.as_mut().expect("Timeout polled after completion");
+        if let Poll::Ready(output) = unsafe { Pin::new_unchecked(future) }.poll(cx) {
+            this.future = None;
+            this.timer.unregister();
+            return Poll::Ready(Ok(output));
+        }
+
+        match unsafe { Pin::new_unchecked(&mut this.timer) }.poll(cx) {
+            Poll::Ready(()) => {
+                this.future = None;
+                Poll::Ready(Err(TimeoutError))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::marker::PhantomPinned;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use super::{Duration, Instant};
+use crate::lock::Lock;
+
+struct TimerNode {
+    deadline: Instant,
+    waker: Option<Waker>,
+    prev: *mut TimerNode,
+    next: *mut TimerNode,
+    queued: bool,
+}
+
+/// Intrusive list of timer nodes, sorted by deadline.
+///
+/// Every node in the queue has a waker.
+struct TimerQueue {
+    head: *mut TimerNode,
+}
+
+unsafe impl Send for TimerQueue {}
+
+impl TimerQueue {
+    /// Insert a node after all nodes with the same or an earlier deadline.
+    ///
+    /// Returns `true` if the node is the new head of the queue.
+    unsafe fn insert(&mut self, node: *mut TimerNode) -> bool {
+        let deadline = (*node).deadline;
+        let mut prev: *mut TimerNode = core::ptr::null_mut();
+        let mut next = self.head;
+        while !next.is_null() && (*next).deadline <= deadline {
+            prev = next;
+            next = (*next).next;
+        }
+
+        (*node).prev = prev;
+        (*node).next = next;
+        (*node).queued = true;
+        if prev.is_null() {
+            self.head = node;
+        } else {
+            (*prev).next = node;
+        }
+        if !next.is_null() {
+            (*next).prev = node;
+        }
+        HAS_TIMERS.store(true, Ordering::Release);
+        prev.is_null()
+    }
+
+    unsafe fn remove(&mut self, node: *mut TimerNode) {
+        let prev = (*node).prev;
+        let next = (*node).next;
+        if prev.is_null() {
+            self.head = next;
+        } else {
+            (*prev).next = next;
+        }
+        if !next.is_null() {
+            (*next).prev = prev;
+        }
+
+        (*node).prev = core::ptr::null_mut();
+        (*node).next = core::ptr::null_mut();
+        (*node).queued = false;
+        HAS_TIMERS.store(!self.head.is_null(), Ordering::Release);
+    }
+
+    /// Remove the first node if it has expired, returning its waker.
+    fn pop_expired(&mut self, now: Instant) -> Option<Waker> {
+        unsafe {
+            let head = self.head;
+            if head.is_null() || (*head).deadline > now {
+                return None;
+            }
+            self.remove(head);
+            (*head).waker.take()
+        }
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        unsafe { self.head.as_ref().map(|node| node.deadline) }
+    }
+}
+
+static QUEUE: Lock<TimerQueue> = Lock::new(TimerQueue {
+    head: core::ptr::null_mut(),
+});
+/// Mirrors whether `QUEUE` is non-empty, letting executors skip taking the lock.
+static HAS_TIMERS: AtomicBool = AtomicBool::new(false);
+
+/// Wake all timers that have expired.
+///
+/// Called continuously by all running executors, including interrupt executors from
+/// their interrupt handler, which takes the `QUEUE` lock in interrupt context.
+pub(crate) fn expire_timers() {
+    if !HAS_TIMERS.load(Ordering::Acquire) {
+        return;
+    }
+    let now = Instant::now();
+    while let Some(waker) = QUEUE.lock(|queue| queue.pop_expired(now)) {
+        waker.wake();
+    }
+}
+
+/// The deadline of the next timer to expire.
+///
+/// Always read under the lock, so that a timer inserted as the new head either is seen
+/// here or sees the idle executor reading it, see `Executor::idle`.
+pub(crate) fn next_deadline() -> Option<Instant> {
+    QUEUE.lock(|queue| queue.next_deadline())
+}
+
+/// A future that completes at a specific instant.
+///
+/// The timer is registered in the timer queue when first polled, and is removed from the
+/// queue when it expires or is dropped. Timers are expired by running executors, so a
+/// thread-mode executor must be running for timers used by interrupt executors to expire.
+/// A timer that becomes the nearest deadline wakes idle executors, so they sleep until it
+/// instead of an earlier computed deadline.
+pub struct Timer {
+    deadline: Instant,
+    registered: bool,
+    node: UnsafeCell<TimerNode>,
+    _pin: PhantomPinned,
+}
+
+unsafe impl Send for Timer {}
+
+impl Timer {
+    /// Create a timer that expires at `deadline`.
+    pub fn at(deadline: Instant) -> Self {
+        Self {
+            deadline,
+            registered: false,
+            node: UnsafeCell::new(TimerNode {
+                deadline,
+                waker: None,
+                prev: core::ptr::null_mut(),
+                next: core::ptr::null_mut(),
+                queued: false,
+            }),
+            _pin: PhantomPinned,
+        }
+    }
+
+    /// Create a timer that expires after `duration` has passed.
+    pub fn after(duration: Duration) -> Self {
+        Self::at(Instant::now() + duration)
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub(super) fn unregister(&mut self) {
+        if self.registered {
+            let node = self.node.get();
+            QUEUE.lock(|queue| unsafe {
+                if (*node).queued {
+                    queue.remove(node);
+                }
+            });
+            self.registered = false;
+        }
+    }
+}
+
+impl Future for Timer {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The node is never moved out of the pinned timer.
+        let this = unsafe { self.get_unchecked_mut() };
+        if Instant::now() >= this.deadline {
+            this.unregister();
+            return Poll::Ready(());
+        }
+
+        let node = this.node.get();
+        let new_head = QUEUE.lock(|queue| unsafe {
+            match &(*node).waker {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                _ => (*node).waker = Some(cx.waker().clone()),
+            }
+            !(*node).queued && queue.insert(node)
+        });
+        this.registered = true;
+        if new_head {
+            // Idle executors may be sleeping until a later deadline, or none at all.
+            crate::executor::signal_deadline_changed();
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}